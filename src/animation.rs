@@ -0,0 +1,327 @@
+#![allow(dead_code)]
+use glam::{Vec3, Vec4, Quat};
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Step,
+    Linear,     // Slerp for rotations
+    Cubic,      // Hermite with the keyframe tangents, Catmull-Rom if they're missing
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoopMode {
+    Once,       // Stops on the last frame
+    Loop,
+    PingPong,   // Plays forwards then backwards
+}
+
+// Values a track can animate, cubic interpolation works on them as a Vec4
+pub trait Animatable: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+    fn to_vec4(self) -> Vec4;
+    fn from_vec4(v: Vec4) -> Self;
+    // Flips other onto the same hemisphere for quaternions
+    fn align(self, _other: Self) -> Self {
+        return self;
+    }
+}
+impl Animatable for Vec3 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        return Vec3::lerp(self, other, t);
+    }
+    fn to_vec4(self) -> Vec4 {
+        return self.extend(0.0);
+    }
+    fn from_vec4(v: Vec4) -> Self {
+        return v.truncate();
+    }
+}
+impl Animatable for Quat {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        return self.slerp(other, t);
+    }
+    fn to_vec4(self) -> Vec4 {
+        return Vec4::from(self);
+    }
+    fn from_vec4(v: Vec4) -> Self {
+        return Quat::from_vec4(v).normalize();
+    }
+    fn align(self, other: Self) -> Self {
+        return if self.dot(other) < 0.0 { -self } else { self };
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    pub tangents: Option<(T, T)>,   // In and out, per second, only used by Cubic
+}
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T) -> Self {
+        Self { time, value, tangents: None }
+    }
+}
+
+// Keys sorted by time, an empty track leaves the value alone
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+    pub interpolation: Interpolation,
+}
+impl<T: Animatable> Track<T> {
+    pub fn new(keys: Vec<Keyframe<T>>, interpolation: Interpolation) -> Self {
+        Self { keys, interpolation }
+    }
+    pub fn empty() -> Self {
+        Self { keys: Vec::new(), interpolation: Interpolation::Linear }
+    }
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+
+        let i = self.keys.partition_point(|k| k.time <= time) - 1;
+        let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+        let dt = b.time - a.time;
+        let t = (time - a.time) / dt;
+        return Some(match self.interpolation {
+            Interpolation::Step => a.value,
+            Interpolation::Linear => a.value.lerp(b.value, t),
+            Interpolation::Cubic => {
+                let (p0, p1) = (a.value.to_vec4(), b.value.align(a.value).to_vec4());
+                let m0 = self.tangent(i, false) * dt;
+                // Tangents are on the hemisphere of their own key, flip along with p1
+                let m1 = self.tangent(i + 1, true) * dt * if p1.dot(b.value.to_vec4()) < 0.0 { -1.0 } else { 1.0 };
+                let (t2, t3) = (t * t, t * t * t);
+                T::from_vec4(
+                    p0 * (2.0 * t3 - 3.0 * t2 + 1.0) + m0 * (t3 - 2.0 * t2 + t)
+                    + p1 * (-2.0 * t3 + 3.0 * t2) + m1 * (t3 - t2)
+                )
+            }
+        });
+    }
+    fn tangent(&self, i: usize, incoming: bool) -> Vec4 {
+        let key = &self.keys[i];
+        if let Some((tangent_in, tangent_out)) = key.tangents {
+            return if incoming { tangent_in } else { tangent_out }.to_vec4();
+        }
+        let prev = &self.keys[i.saturating_sub(1)];
+        let next = &self.keys[(i + 1).min(self.keys.len() - 1)];
+        if next.time <= prev.time {
+            return Vec4::ZERO;
+        }
+        let (p, n) = (prev.value.align(key.value).to_vec4(), next.value.align(key.value).to_vec4());
+        return (n - p) / (next.time - prev.time);
+    }
+}
+
+// Anything an Animation can play
+pub trait Timeline {
+    fn duration(&self) -> f32;
+}
+
+// Keyframed transform, shared between everything playing it
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: String,
+    pub duration: f32,
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Quat>,
+    pub scale: Track<Vec3>,
+}
+impl Clip {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), duration: 0.0, translation: Track::empty(), rotation: Track::empty(), scale: Track::empty() }
+    }
+    // Duration is the time of the last key of any track
+    pub fn with_tracks(mut self, translation: Track<Vec3>, rotation: Track<Quat>, scale: Track<Vec3>) -> Self {
+        let ends = [translation.keys.last().map(|k| k.time), rotation.keys.last().map(|k| k.time), scale.keys.last().map(|k| k.time)];
+        self.duration = ends.into_iter().flatten().fold(0.0, f32::max);
+        (self.translation, self.rotation, self.scale) = (translation, rotation, scale);
+        return self;
+    }
+    // Only the animated parts are overwritten
+    pub fn apply(&self, time: f32, translation: &mut Vec3, rotation: &mut Quat, scale: &mut Vec3) {
+        if let Some(t) = self.translation.sample(time) {
+            *translation = t;
+        }
+        if let Some(r) = self.rotation.sample(time) {
+            *rotation = r;
+        }
+        if let Some(s) = self.scale.sample(time) {
+            *scale = s;
+        }
+    }
+}
+
+impl Timeline for Clip {
+    fn duration(&self) -> f32 {
+        return self.duration;
+    }
+}
+
+// Vertex animation, every frame is a morph target of the mesh (see Mesh::frame_clips)
+#[derive(Clone, Debug)]
+pub struct FrameClip {
+    pub name: String,
+    pub frames: Vec<usize>,     // Indices into Mesh::morph_targets, in playing order
+    pub fps: f32,
+}
+impl FrameClip {
+    pub fn new(name: &str, frames: Vec<usize>, fps: f32) -> Self {
+        Self { name: name.to_string(), frames, fps }
+    }
+    // Morph weights blending the two frames around time, wrapping goes from the last frame back
+    // to the first, otherwise the last one is held
+    pub fn weights(&self, time: f32, wrap: bool) -> Vec<f32> {
+        let Some(&last) = self.frames.iter().max() else { return Vec::new() };
+        let mut weights = vec![0.0; last + 1];
+        let f = (time * self.fps).max(0.0);
+        let a = (f as usize).min(self.frames.len() - 1);
+        let b = if wrap { (a + 1) % self.frames.len() } else { (a + 1).min(self.frames.len() - 1) };
+        let t = (f - a as f32).min(1.0);
+        weights[self.frames[a]] += 1.0 - t;
+        weights[self.frames[b]] += t;
+        return weights;
+    }
+}
+impl Timeline for FrameClip {
+    fn duration(&self) -> f32 {
+        return self.frames.len() as f32 / self.fps;
+    }
+}
+
+// A clip being played, attach to a Model or a scene Node
+// Skeleton clips play the same way, see Model::skeleton_animation
+#[derive(Clone, Debug)]
+pub struct Animation<C = Clip> {
+    pub clip: Rc<C>,
+    pub time: f32,      // Seconds since the start, see local_time for the clip time
+    pub speed: f32,
+    pub mode: LoopMode,
+    pub playing: bool,
+}
+impl<C: Timeline> Animation<C> {
+    pub fn new(clip: Rc<C>, mode: LoopMode) -> Self {
+        Self { clip, time: 0.0, speed: 1.0, mode, playing: true }
+    }
+    pub fn update(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt * self.speed;
+        let duration = self.clip.duration();
+        if self.mode == LoopMode::Once && (self.time >= duration || self.time < 0.0) {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+    }
+    pub fn local_time(&self) -> f32 {
+        let duration = self.clip.duration();
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        return match self.mode {
+            LoopMode::Once => self.time.clamp(0.0, duration),
+            LoopMode::Loop => self.time.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let t = self.time.rem_euclid(duration * 2.0);
+                if t > duration { duration * 2.0 - t } else { t }
+            }
+        };
+    }
+}
+impl Animation<Clip> {
+    pub fn apply(&self, translation: &mut Vec3, rotation: &mut Quat, scale: &mut Vec3) {
+        self.clip.apply(self.local_time(), translation, rotation, scale);
+    }
+}
+impl Animation<FrameClip> {
+    pub fn morph_weights(&self) -> Vec<f32> {
+        return self.clip.weights(self.local_time(), self.mode == LoopMode::Loop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(values: &[f32], interpolation: Interpolation) -> Track<Vec3> {
+        let keys = values.iter().enumerate().map(|(i, &v)| Keyframe::new(i as f32, Vec3::splat(v))).collect();
+        return Track::new(keys, interpolation);
+    }
+    fn sample(track: &Track<Vec3>, time: f32) -> f32 {
+        return track.sample(time).unwrap().x;
+    }
+
+    #[test]
+    fn holds_the_ends() {
+        assert!(Track::<Vec3>::empty().sample(1.0).is_none());
+        for interpolation in [Interpolation::Step, Interpolation::Linear, Interpolation::Cubic] {
+            let track = track(&[1.0, 3.0, 2.0], interpolation);
+            assert_eq!(sample(&track, -1.0), 1.0);
+            assert_eq!(sample(&track, 1.0), 3.0);
+            assert_eq!(sample(&track, 5.0), 2.0);
+        }
+    }
+
+    #[test]
+    fn interpolates_between_keys() {
+        assert_eq!(sample(&track(&[1.0, 3.0, 2.0], Interpolation::Step), 1.9), 3.0);
+        assert_eq!(sample(&track(&[1.0, 3.0, 2.0], Interpolation::Linear), 1.25), 2.75);
+        // Catmull-Rom keeps evenly spaced keys on a line
+        assert!((sample(&track(&[0.0, 1.0, 2.0], Interpolation::Cubic), 0.5) - 0.5).abs() < 1e-6);
+        assert!((sample(&track(&[0.0, 1.0, 2.0], Interpolation::Cubic), 1.25) - 1.25).abs() < 1e-6);
+        // Flat tangents ease in and out
+        let mut eased = track(&[0.0, 1.0], Interpolation::Cubic);
+        eased.keys.iter_mut().for_each(|k| k.tangents = Some((Vec3::ZERO, Vec3::ZERO)));
+        assert!((sample(&eased, 0.25) - 0.15625).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotations_take_the_short_way() {
+        let (a, b) = (Quat::IDENTITY, Quat::from_rotation_y(90f32.to_radians()));
+        let halfway = Quat::from_rotation_y(45f32.to_radians());
+        for interpolation in [Interpolation::Linear, Interpolation::Cubic] {
+            // -b is the same rotation on the far hemisphere
+            let track = Track::new(vec![Keyframe::new(0.0, a), Keyframe::new(1.0, -b)], interpolation);
+            let q = track.sample(0.5).unwrap();
+            assert!(q.angle_between(halfway) < 1e-3, "{:?}: {:?}", interpolation, q);
+        }
+    }
+
+    #[test]
+    fn loop_modes() {
+        let clip = Rc::new(Clip::new("c").with_tracks(track(&[0.0, 1.0, 2.0], Interpolation::Linear), Track::empty(), Track::empty()));
+        let local = |mode: LoopMode, dt: f32| {
+            let mut animation = Animation::new(clip.clone(), mode);
+            animation.update(dt);
+            (animation.local_time(), animation.playing)
+        };
+        assert_eq!(local(LoopMode::Once, 2.5), (2.0, false));
+        assert_eq!(local(LoopMode::Loop, 2.5), (0.5, true));
+        assert_eq!(local(LoopMode::PingPong, 2.5), (1.5, true));
+    }
+
+    #[test]
+    fn frame_clips_blend_neighbouring_frames() {
+        // Frames are morph targets 4, 2 and 3
+        let clip = FrameClip::new("run", vec![4, 2, 3], 10.0);
+        assert_eq!(clip.weights(0.0, true), [0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(clip.weights(0.125, true), [0.0, 0.0, 0.75, 0.25, 0.0]);
+        // Past the last frame, wrapping blends back to the first one, otherwise it's held
+        assert_eq!(clip.weights(0.25, true), [0.0, 0.0, 0.0, 0.5, 0.5]);
+        assert_eq!(clip.weights(0.25, false), [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(clip.weights(5.0, false), [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(clip.weights(-1.0, false), [0.0, 0.0, 0.0, 0.0, 1.0]);
+        // A single frame blends with itself
+        assert_eq!(FrameClip::new("pose", vec![1], 10.0).weights(0.05, true), [0.0, 1.0]);
+        assert!(FrameClip::new("empty", Vec::new(), 10.0).weights(0.0, true).is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::fs;
+use std::time::SystemTime;
+
+use crate::mesh::Mesh;
+use crate::utils::try_load_pixels;
+
+// Pixels, width, height
+pub type Texture = (Vec<u32>, u32, u32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureHandle(u32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MeshHandle(u32);
+
+struct Entry<T> {
+    asset: T,
+    name: String,   // File path for loaded assets
+    refs: u32,
+    modified: Option<SystemTime>,   // Only set for assets loaded from a file
+    error: Option<String>,  // Last failed reload, the previous asset is kept
+}
+
+// Assets of one type, handles are never reused so a stale one just finds nothing
+struct Pool<T> {
+    entries: HashMap<u32, Entry<T>>,
+    names: HashMap<String, u32>,
+    next: u32,
+}
+impl<T> Pool<T> {
+    fn new() -> Self {
+        Self { entries: HashMap::new(), names: HashMap::new(), next: 0 }
+    }
+    // Same name returns the existing asset with one more reference
+    fn load(&mut self, name: &str, load: impl FnOnce() -> Result<T, String>) -> Result<u32, String> {
+        if let Some(id) = self.find(name) {
+            self.entries.get_mut(&id).unwrap().refs += 1;
+            return Ok(id);
+        }
+        let id = self.add(name, load()?);
+        self.entries.get_mut(&id).unwrap().modified = modified(name);
+        return Ok(id);
+    }
+    // Replaces any asset with the same name, old handles to it stay valid
+    // The caller gets its own reference like with load, so one unload doesn't free it for the others
+    fn add(&mut self, name: &str, asset: T) -> u32 {
+        if let Some(id) = self.find(name) {
+            let entry = self.entries.get_mut(&id).unwrap();
+            entry.asset = asset;
+            entry.refs += 1;
+            return id;
+        }
+        let id = self.next;
+        self.next += 1;
+        self.entries.insert(id, Entry { asset, name: name.to_string(), refs: 1, modified: None, error: None });
+        self.names.insert(name.to_string(), id);
+        return id;
+    }
+    fn find(&self, name: &str) -> Option<u32> {
+        return self.names.get(name).copied();
+    }
+    fn get(&self, id: u32) -> Option<&T> {
+        return self.entries.get(&id).map(|e| &e.asset);
+    }
+    fn set(&mut self, id: u32, asset: T) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.asset = asset;
+        }
+    }
+    fn name(&self, id: u32) -> Option<&str> {
+        return self.entries.get(&id).map(|e| e.name.as_str());
+    }
+    // Reloads every file that changed since it was loaded, handles stay the same
+    // Returns true if anything was reloaded or failed to
+    fn reload_changed(&mut self, load: impl Fn(&str) -> Result<T, String>) -> bool {
+        let mut changed = false;
+        for entry in self.entries.values_mut() {
+            let Some(last) = entry.modified else { continue };
+            let Some(now) = modified(&entry.name) else { continue };
+            if now <= last {
+                continue;
+            }
+            entry.modified = Some(now);
+            changed = true;
+            match load(&entry.name) {
+                Ok(asset) => { entry.asset = asset; entry.error = None; }
+                Err(e) => entry.error = Some(e),
+            }
+        }
+        return changed;
+    }
+    fn errors(&self) -> impl Iterator<Item = &String> {
+        return self.entries.values().filter_map(|e| e.error.as_ref());
+    }
+    // Drops one reference, the asset is freed once none are left
+    fn unload(&mut self, id: u32) {
+        let Some(entry) = self.entries.get_mut(&id) else { return };
+        entry.refs -= 1;
+        if entry.refs == 0 {
+            let entry = self.entries.remove(&id).unwrap();
+            self.names.remove(&entry.name);
+        }
+    }
+}
+
+// Owns every texture and mesh, models and materials only keep handles
+pub struct Assets {
+    textures: Pool<Texture>,
+    meshes: Pool<Mesh>,
+}
+impl Assets {
+    pub fn new() -> Self {
+        Self { textures: Pool::new(), meshes: Pool::new() }
+    }
+    pub fn load_texture(&mut self, path: &str) -> TextureHandle {
+        return self.try_load_texture(path).unwrap_or_else(|e| panic!("{}", e));
+    }
+    pub fn try_load_texture(&mut self, path: &str) -> Result<TextureHandle, String> {
+        return self.textures.load(path, || try_load_pixels(path)).map(TextureHandle);
+    }
+    // Textures that don't come from a file (render textures), name is used for lookup only
+    pub fn add_texture(&mut self, name: &str, texture: Texture) -> TextureHandle {
+        return TextureHandle(self.textures.add(name, texture));
+    }
+    pub fn texture(&self, handle: TextureHandle) -> Option<&Texture> {
+        return self.textures.get(handle.0);
+    }
+    pub fn set_texture(&mut self, handle: TextureHandle, texture: Texture) {
+        self.textures.set(handle.0, texture);
+    }
+    pub fn find_texture(&self, name: &str) -> Option<TextureHandle> {
+        return self.textures.find(name).map(TextureHandle);
+    }
+    pub fn texture_name(&self, handle: TextureHandle) -> Option<&str> {
+        return self.textures.name(handle.0);
+    }
+    pub fn unload_texture(&mut self, handle: TextureHandle) {
+        self.textures.unload(handle.0);
+    }
+    pub fn load_mesh(&mut self, path: &str) -> MeshHandle {
+        return self.try_load_mesh(path).unwrap_or_else(|e| panic!("{}", e));
+    }
+    pub fn try_load_mesh(&mut self, path: &str) -> Result<MeshHandle, String> {
+        return self.meshes.load(path, || Mesh::load(path)).map(MeshHandle);
+    }
+    pub fn add_mesh(&mut self, name: &str, mesh: Mesh) -> MeshHandle {
+        return MeshHandle(self.meshes.add(name, mesh));
+    }
+    pub fn mesh(&self, handle: MeshHandle) -> Option<&Mesh> {
+        return self.meshes.get(handle.0);
+    }
+    pub fn set_mesh(&mut self, handle: MeshHandle, mesh: Mesh) {
+        self.meshes.set(handle.0, mesh);
+    }
+    pub fn find_mesh(&self, name: &str) -> Option<MeshHandle> {
+        return self.meshes.find(name).map(MeshHandle);
+    }
+    pub fn mesh_name(&self, handle: MeshHandle) -> Option<&str> {
+        return self.meshes.name(handle.0);
+    }
+    pub fn unload_mesh(&mut self, handle: MeshHandle) {
+        self.meshes.unload(handle.0);
+    }
+    // Polls the modification time of every loaded file and reloads the changed ones in place
+    // Files that fail to load keep the old asset, see errors
+    pub fn reload_changed(&mut self) -> bool {
+        let textures = self.textures.reload_changed(try_load_pixels);
+        let meshes = self.meshes.reload_changed(Mesh::load);
+        return textures || meshes;
+    }
+    // Errors from the last reload of every file that is still broken
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors: Vec<String> = self.textures.errors().chain(self.meshes.errors()).cloned().collect();
+        errors.sort();
+        return errors;
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    return fs::metadata(path).and_then(|m| m.modified()).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adding_a_name_twice_takes_a_reference() {
+        let mut assets = Assets::new();
+        let first = assets.add_texture("shared", (vec![1], 1, 1));
+        let second = assets.add_texture("shared", (vec![2], 1, 1));
+        assert_eq!(first, second);
+        assert_eq!(assets.texture(first).unwrap().0, vec![2]);
+        assets.unload_texture(first);
+        assert!(assets.texture(second).is_some());
+        assets.unload_texture(second);
+        assert!(assets.texture(second).is_none());
+    }
+}
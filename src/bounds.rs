@@ -0,0 +1,135 @@
+use glam::{Vec3, Vec4, Mat4, Vec4Swizzles};
+
+use crate::camera::Projection;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+impl Aabb {
+    // Empty input gives a box at the origin
+    pub fn from_points(points: impl Iterator<Item = Vec3>) -> Self {
+        let mut aabb = Self { min: Vec3::splat(f32::MAX), max: Vec3::splat(f32::MIN) };
+        for p in points {
+            aabb.min = aabb.min.min(p);
+            aabb.max = aabb.max.max(p);
+        }
+        if aabb.min.x > aabb.max.x {
+            return Self::default();
+        }
+        return aabb;
+    }
+    pub fn center(&self) -> Vec3 {
+        return (self.min + self.max) * 0.5;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+impl Sphere {
+    // Centered on the box, radius reaches the farthest point
+    pub fn from_points(points: impl Iterator<Item = Vec3> + Clone) -> Self {
+        let center = Aabb::from_points(points.clone()).center();
+        let radius = points.map(|p| p.distance_squared(center)).fold(0.0, f32::max).sqrt();
+        return Self { center, radius };
+    }
+    // Stays a sphere under non uniform scale by using the largest axis
+    pub fn transform(&self, mat: Mat4) -> Self {
+        let scale = mat.x_axis.xyz().length().max(mat.y_axis.xyz().length()).max(mat.z_axis.xyz().length());
+        return Self { center: mat.transform_point3(self.center), radius: self.radius * scale };
+    }
+    // Holds the mesh in any pose blended from these skinning matrices (or left unweighted)
+    // A skinned vertex is a weighted average of the vertex moved by each joint, each of those
+    // stays inside the sphere moved by that joint, so one sphere around all of them holds it too
+    pub fn skinned(&self, joints: &[Mat4]) -> Self {
+        let radius = joints.iter()
+            .map(|j| { let moved = self.transform(*j); moved.center.distance(self.center) + moved.radius })
+            .fold(self.radius, f32::max);
+        return Self { center: self.center, radius };
+    }
+}
+
+// World space planes, xyz is the normal pointing inside, w the distance
+// Near and the four sides, nothing gets clipped by the far plane so neither is culled
+pub struct Frustum {
+    pub planes: [Vec4; 5],
+}
+impl Frustum {
+    pub fn new(projection: &Projection, mat_view: Mat4) -> Self {
+        // Corners at two view depths, works for any projection the pipeline accepts
+        let mat_proj = projection.matrix();
+        let inv_proj = mat_proj.inverse();
+        let corner = |x: f32, y: f32, depth: f32| {
+            let ndc_z = mat_proj.project_point3(Vec3::new(0.0, 0.0, depth)).z;
+            inv_proj.project_point3(Vec3::new(x, y, ndc_z))
+        };
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let near = corners.map(|(x, y)| corner(x, y, 1.0));
+        let far = corners.map(|(x, y)| corner(x, y, 2.0));
+        let inside = (near.iter().sum::<Vec3>() + far.iter().sum::<Vec3>()) / 8.0;
+
+        let mut planes = [Vec4::new(0.0, 0.0, 1.0, -projection.near()); 5];  // Near clip plane of the pipeline
+        for i in 0..4 {
+            let (a, b, c) = (near[i], near[(i + 1) % 4], far[i]);
+            let mut n = (b - a).cross(c - a).normalize();
+            if n.dot(inside - a) < 0.0 {
+                n = -n;
+            }
+            planes[i + 1] = n.extend(-n.dot(a));
+        }
+        // View to world, planes transform by the transpose of the inverse of world to view
+        return Self { planes: planes.map(|p| mat_view.transpose() * p) };
+    }
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        return self.planes.iter().all(|p| p.xyz().dot(sphere.center) + p.w >= -sphere.radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visible(frustum: &Frustum, center: Vec3, radius: f32) -> bool {
+        return frustum.intersects_sphere(&Sphere { center, radius });
+    }
+
+    #[test]
+    fn perspective_culls_outside_the_sides_and_near() {
+        // 90 degrees vertical, the top plane is y = z
+        let frustum = Frustum::new(&Projection::perspective(90.0, 0.1, 100.0), Mat4::IDENTITY);
+        assert!(visible(&frustum, Vec3::new(0.0, 0.0, 5.0), 0.1));
+        assert!(!visible(&frustum, Vec3::new(0.0, 7.0, 5.0), 1.0));
+        assert!(visible(&frustum, Vec3::new(0.0, 7.0, 5.0), 1.5));
+        assert!(!visible(&frustum, Vec3::new(0.0, -7.0, 5.0), 1.0));
+        assert!(!visible(&frustum, Vec3::new(0.0, 0.0, -5.0), 1.0));
+        assert!(visible(&frustum, Vec3::new(0.0, 0.0, -5.0), 6.0));
+        // Wider than tall
+        assert!(visible(&frustum, Vec3::new(7.0, 0.0, 5.0), 0.1));
+        assert!(!visible(&frustum, Vec3::new(12.0, 0.0, 5.0), 0.1));
+        // Nothing is past the far plane
+        assert!(visible(&frustum, Vec3::new(0.0, 0.0, 5000.0), 0.1));
+    }
+
+    #[test]
+    fn planes_follow_the_view_matrix() {
+        let frustum = Frustum::new(&Projection::perspective(90.0, 0.1, 100.0), Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(visible(&frustum, Vec3::new(0.0, 0.0, 15.0), 0.1));
+        assert!(!visible(&frustum, Vec3::new(0.0, 0.0, 5.0), 0.1));
+        assert!(!visible(&frustum, Vec3::new(0.0, 7.0, 15.0), 1.0));
+    }
+
+    #[test]
+    fn orthographic_sides_are_parallel() {
+        let frustum = Frustum::new(&Projection::orthographic(4.0, 2.0, 0.5, 10.0), Mat4::IDENTITY);
+        assert!(visible(&frustum, Vec3::new(1.9, 0.0, 3.0), 0.05));
+        assert!(visible(&frustum, Vec3::new(1.9, 0.0, 300.0), 0.05));
+        assert!(!visible(&frustum, Vec3::new(2.5, 0.0, 3.0), 0.4));
+        assert!(visible(&frustum, Vec3::new(2.5, 0.0, 3.0), 0.6));
+        assert!(!visible(&frustum, Vec3::new(0.0, 1.5, 3.0), 0.4));
+        assert!(!visible(&frustum, Vec3::new(0.0, 0.0, 0.2), 0.2));
+    }
+}
@@ -0,0 +1,189 @@
+use glam::{Vec2, Vec3, Mat4};
+
+use crate::bounds::Aabb;
+use crate::mesh::Triangle;
+
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub dir: Vec3,
+}
+impl Ray {
+    // dir isn't normalized again so t stays the same in both spaces
+    pub fn transform(&self, mat: Mat4) -> Self {
+        Self { origin: mat.transform_point3(self.origin), dir: mat.transform_vector3(self.dir) }
+    }
+    pub fn at(&self, t: f32) -> Vec3 {
+        return self.origin + self.dir * t;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub t: f32,
+    pub triangle: usize,    // Index into Mesh::triangles
+    pub bary: Vec3,         // Weights of the three vertices
+    pub uv: Vec2,
+}
+
+// Leaves hold count triangles from first in Bvh::indices, inner nodes have count 0
+// and their children at first and first + 1
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    aabb: Aabb,
+    first: usize,
+    count: usize,
+}
+
+// Bounding volume hierarchy over a mesh's triangles, median split on the longest axis
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+impl Bvh {
+    pub fn new(triangles: &[Triangle]) -> Self {
+        let mut bvh = Self { nodes: Vec::new(), indices: (0..triangles.len()).collect() };
+        if triangles.is_empty() {
+            return bvh;
+        }
+        let centroids: Vec<Vec3> = triangles.iter().map(|t| (t.v[0].pos + t.v[1].pos + t.v[2].pos) / 3.0).collect();
+        bvh.nodes.push(BvhNode { aabb: Aabb::default(), first: 0, count: triangles.len() });
+        bvh.split(0, triangles, &centroids);
+        return bvh;
+    }
+    fn split(&mut self, node: usize, triangles: &[Triangle], centroids: &[Vec3]) {
+        let BvhNode { first, count, .. } = self.nodes[node];
+        let range = first..first + count;
+        self.nodes[node].aabb = Aabb::from_points(self.indices[range.clone()].iter().flat_map(|&i| triangles[i].v.iter().map(|v| v.pos)));
+        if count <= LEAF_SIZE {
+            return;
+        }
+
+        let bounds = Aabb::from_points(self.indices[range.clone()].iter().map(|&i| centroids[i]));
+        let size = bounds.max - bounds.min;
+        let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+        let half = count / 2;
+        self.indices[range].select_nth_unstable_by(half, |&a, &b| centroids[a][axis].total_cmp(&centroids[b][axis]));
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { aabb: Aabb::default(), first, count: half });
+        self.nodes.push(BvhNode { aabb: Aabb::default(), first: first + half, count: count - half });
+        self.nodes[node] = BvhNode { aabb: self.nodes[node].aabb, first: left, count: 0 };
+        self.split(left, triangles, centroids);
+        self.split(left + 1, triangles, centroids);
+    }
+    // Closest hit in front of the ray origin, triangles must be the ones the BVH was built from
+    pub fn intersect(&self, triangles: &[Triangle], ray: &Ray) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let Some(node) = self.nodes.get(node) else { continue };
+            let max_t = best.map_or(f32::MAX, |h| h.t);
+            if !ray_aabb(ray, &node.aabb, max_t) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+                continue;
+            }
+            for &i in &self.indices[node.first..node.first + node.count] {
+                let tri = &triangles[i];
+                let Some((t, u, v)) = ray_triangle(ray, tri.v[0].pos, tri.v[1].pos, tri.v[2].pos) else { continue };
+                if t < best.map_or(f32::MAX, |h| h.t) {
+                    let bary = Vec3::new(1.0 - u - v, u, v);
+                    let uv = tri.v[0].texture * bary.x + tri.v[1].texture * bary.y + tri.v[2].texture * bary.z;
+                    best = Some(Hit { t, triangle: i, bary, uv });
+                }
+            }
+        }
+        return best;
+    }
+}
+
+// Slab test, true if the box is hit between 0 and max_t
+fn ray_aabb(ray: &Ray, aabb: &Aabb, max_t: f32) -> bool {
+    let inv = ray.dir.recip();
+    let t1 = (aabb.min - ray.origin) * inv;
+    let t2 = (aabb.max - ray.origin) * inv;
+    let near = t1.min(t2).max_element().max(0.0);
+    let far = t1.max(t2).min_element().min(max_t);
+    return near <= far;
+}
+
+// Möller–Trumbore, both sides count, returns t and the weights of b and c
+pub fn ray_triangle(ray: &Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<(f32, f32, f32)> {
+    let (e1, e2) = (b - a, c - a);
+    let p = ray.dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    return if t > 0.0 { Some((t, u, v)) } else { None };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+
+    fn brute_force(triangles: &[Triangle], ray: &Ray) -> Option<(f32, usize)> {
+        return triangles.iter().enumerate()
+            .filter_map(|(i, tri)| ray_triangle(ray, tri.v[0].pos, tri.v[1].pos, tri.v[2].pos).map(|(t, _, _)| (t, i)))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    #[test]
+    fn matches_a_brute_force_loop() {
+        let mesh = Mesh::from_obj("models/arctic.obj").unwrap();
+        let bvh = Bvh::new(&mesh.triangles);
+        let aabb = Aabb::from_points(mesh.triangles.iter().flat_map(|t| t.v.iter().map(|v| v.pos)));
+        let (center, size) = (aabb.center(), aabb.max - aabb.min);
+
+        // Rays from all around aimed at points inside the box, some miss
+        let mut seed = 1u32;
+        let mut random = || { seed = seed.wrapping_mul(1664525).wrapping_add(1013904223); (seed >> 8) as f32 / (1 << 24) as f32 - 0.5 };
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = center + Vec3::new(random(), random(), random()).normalize() * size.length() * 2.0;
+            let target = center + Vec3::new(random(), random(), random()) * size;
+            let ray = Ray { origin, dir: target - origin };
+            let expected = brute_force(&mesh.triangles, &ray);
+            let hit = bvh.intersect(&mesh.triangles, &ray);
+            assert_eq!(hit.is_some(), expected.is_some());
+            let (Some(hit), Some((t, triangle))) = (hit, expected) else { continue };
+            // Ties on shared edges can pick either triangle
+            assert_eq!(hit.t, t);
+            let tri = &mesh.triangles[hit.triangle];
+            assert!(hit.triangle == triangle || ray_triangle(&ray, tri.v[0].pos, tri.v[1].pos, tri.v[2].pos).is_some_and(|h| h.0 == t));
+            hits += 1;
+        }
+        assert!(hits > 100 && hits < 500, "{} hits", hits);
+    }
+
+    #[test]
+    fn empty_and_behind() {
+        let ray = Ray { origin: Vec3::ZERO, dir: Vec3::Z };
+        assert!(Bvh::new(&[]).intersect(&[], &ray).is_none());
+        let mesh = Mesh::from_obj("models/arctic.obj").unwrap();
+        let bvh = Bvh::new(&mesh.triangles);
+        let aabb = Aabb::from_points(mesh.triangles.iter().flat_map(|t| t.v.iter().map(|v| v.pos)));
+        let origin = aabb.center() - Vec3::Z * (aabb.max - aabb.min).length() * 2.0;
+        assert!(bvh.intersect(&mesh.triangles, &Ray { origin, dir: -Vec3::Z }).is_none());
+    }
+}
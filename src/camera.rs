@@ -1,6 +1,64 @@
-use glam::{Vec3, Mat4};
+use glam::{Vec3, Vec4, Mat4};
 
+use crate::bvh::Ray;
+use crate::canvas::{HEIGHT, WIDTH};
+
+// Everything in front of the camera is at view space z > near (the camera looks down -front).
+// Perspective matrices get there with a negative w, which also flips x and y, so the orthographic
+// one flips them itself. Depth is bigger closer to the camera, 0 is left for empty pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    Perspective { fov: f32, aspect: f32, near: f32, far: Option<f32> },     // Vertical fov in degrees, no far is infinite
+    Orthographic { left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32 },   // View space bounds, view +x is the canvas left
+}
+impl Projection {
+    // Aspect of the canvas
+    pub fn perspective(fov: f32, near: f32, far: f32) -> Self {
+        return Self::Perspective { fov, aspect: WIDTH as f32 / HEIGHT as f32, near, far: Some(far) };
+    }
+    pub fn infinite(fov: f32, near: f32) -> Self {
+        return Self::Perspective { fov, aspect: WIDTH as f32 / HEIGHT as f32, near, far: None };
+    }
+    // Centered on the camera, width and height in world units
+    pub fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self {
+        return Self::Orthographic { left: -width * 0.5, right: width * 0.5, bottom: -height * 0.5, top: height * 0.5, near, far };
+    }
+    pub fn matrix(&self) -> Mat4 {
+        return match *self {
+            Self::Perspective { fov, aspect, near, far: Some(far) } => Mat4::perspective_rh(fov.to_radians(), aspect, near, far),
+            Self::Perspective { fov, aspect, near, far: None } => Mat4::perspective_infinite_rh(fov.to_radians(), aspect, near),
+            // Depth goes from 2 at near to 1 at far, about what the perspective one gives
+            Self::Orthographic { left, right, bottom, top, near, far } => Mat4::from_cols(
+                Vec4::new(-2.0 / (right - left), 0.0, 0.0, 0.0),
+                Vec4::new(0.0, -2.0 / (top - bottom), 0.0, 0.0),
+                Vec4::new(0.0, 0.0, -1.0 / (far - near), 0.0),
+                Vec4::new((left + right) / (right - left), (top + bottom) / (top - bottom), 1.0 + far / (far - near), 1.0),
+            ),
+        };
+    }
+    pub fn near(&self) -> f32 {
+        return match *self {
+            Self::Perspective { near, .. } | Self::Orthographic { near, .. } => near,
+        };
+    }
+    // Direction from the eye to a view space point, for back face culling
+    pub fn eye_to(&self, view: Vec3) -> Vec3 {
+        return match self {
+            Self::Perspective { .. } => view,
+            Self::Orthographic { .. } => Vec3::Z,
+        };
+    }
+}
+pub const DEFAULT_FOV: f32 = 90.0;     // Vertical, in degrees
+impl Default for Projection {
+    fn default() -> Self {
+        return Self::perspective(DEFAULT_FOV, 0.1, 1000.0);
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
+    pub projection: Projection,
     pos: Vec3,
     speed: f32,
     front: Vec3,
@@ -12,7 +70,8 @@ pub struct Camera {
 impl Camera {
     pub fn new(pos: Vec3, speed: f32, sensitivity: f32) -> Self {
         Self {
-            pos, 
+            projection: Projection::default(),
+            pos,
             speed,
             front: Vec3::new(0.0, 0.0, -1.0),
             up: Vec3::new(0.0, 1.0, 0.0),
@@ -24,6 +83,24 @@ pub fn new(pos: Vec3, speed: f32, sensitivity: f32) -> Self {
     pub fn get_view_mat(&self) -> Mat4 {
         return Mat4::look_at_rh(self.pos, self.pos + self.front, self.up);
     }
+    // Places the camera like a scene node, looking down the node's +z
+    pub fn set_transform(&mut self, world: Mat4) {
+        self.pos = world.transform_point3(Vec3::ZERO);
+        self.front = -world.transform_vector3(Vec3::Z).normalize();
+        self.up = world.transform_vector3(Vec3::Y).normalize();
+    }
+    // World space ray through a canvas pixel, starting on the near clip plane
+    pub fn screen_ray(&self, x: f32, y: f32) -> Ray {
+        let mat_proj = self.projection.matrix();
+        let inv = (mat_proj * self.get_view_mat()).inverse();
+        let ndc_x = x / (0.5 * WIDTH as f32) - 1.0;
+        let ndc_y = y / (0.5 * HEIGHT as f32) - 1.0;
+        let depth = |z: f32| mat_proj.project_point3(Vec3::new(0.0, 0.0, z)).z;
+        let near = self.projection.near();
+        let far = inv.project_point3(Vec3::new(ndc_x, ndc_y, depth(near + 1.0)));
+        let near = inv.project_point3(Vec3::new(ndc_x, ndc_y, depth(near)));
+        return Ray { origin: near, dir: (far - near).normalize() };
+    }
     pub fn move_forward(&mut self) {
         self.pos -= self.front * self.speed;
     }
@@ -40,9 +117,12 @@ pub fn look(&mut self, x: f32, y: f32) {
         let x_offset = x * self.sensitivity;
         let y_offset = y * self.sensitivity;
 
-        self.yaw -= x_offset;
-        self.pitch -= y_offset;
-        self.pitch = self.pitch.clamp(-89.0, 89.0);
+        self.set_rotation(self.yaw - x_offset, self.pitch - y_offset);
+    }
+    // Yaw and pitch in degrees, yaw 270 looks down -z
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89.0, 89.0);
 
         let direction = Vec3::new(
             self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
@@ -52,3 +132,17 @@ pub fn look(&mut self, x: f32, y: f32) {
         self.front = direction.normalize();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_bounds_map_to_ndc() {
+        let projection = Projection::Orthographic { left: 0.0, right: 2.0, bottom: -1.0, top: 3.0, near: 0.5, far: 10.0 };
+        let mat = projection.matrix();
+        let ndc = |p: Vec3| mat.project_point3(p);
+        assert!(ndc(Vec3::new(0.0, -1.0, 0.5)).abs_diff_eq(Vec3::new(1.0, 1.0, 2.0), 1e-5));
+        assert!(ndc(Vec3::new(2.0, 3.0, 10.0)).abs_diff_eq(Vec3::new(-1.0, -1.0, 1.0), 1e-5));
+    }
+}
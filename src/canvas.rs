@@ -1,11 +1,19 @@
 use glam::Vec3;
 
+use crate::color::{Color, BlendMode};
+
 // Hardcoded so i can avoid vectors, will only have one canvas either way
 pub const W_WIDTH: u32 = 1600;  // Screen size
 pub const W_HEIGHT: u32 = 900;  // Screen size
 pub const WIDTH: usize = (W_WIDTH / 1) as usize;   // Viewport size
 pub const HEIGHT: usize = (W_HEIGHT / 1) as usize;  // Viewport size
 
+// Anything the rasterizer can write fragment shader output into
+pub trait RenderTarget<O> {
+    fn canvas(&mut self) -> &mut Canvas;    // Depth buffer, wireframe is drawn here too
+    fn write(&mut self, x: i32, y: i32, z: f32, out: O, blend: BlendMode);
+}
+
 pub struct Canvas {
     pub pixels: Box<[u32; WIDTH * HEIGHT]>,
     pub depth: Box<[f32; WIDTH * HEIGHT]>,
@@ -17,23 +25,32 @@ pub fn new() -> Self {
             depth: Box::new([0f32; WIDTH * HEIGHT])
         }
     }
-    pub fn clear(&mut self, color: u32) {
-        self.pixels.fill(color);
+    pub fn clear(&mut self, color: Color) {
+        self.pixels.fill(color.to_u32());
         self.depth.fill(0.0);
     }
-    pub fn put_pixel(&mut self, x: i32, y: i32, z:f32, color: u32) {
+    pub fn put_pixel(&mut self, x: i32, y: i32, z:f32, color: Color) {
         if z > self.get_depth(x, y) {
-            self.pixels[(x + y * WIDTH as i32) as usize] = color;
+            self.pixels[(x + y * WIDTH as i32) as usize] = color.to_u32();
             self.put_depth(x, y, z);
         }
     }
+    // Depth tested but doesn't write depth, used for the transparent pass
+    pub fn blend_pixel(&mut self, x: i32, y: i32, z: f32, color: Color, mode: BlendMode) {
+        if mode == BlendMode::Opaque {
+            self.put_pixel(x, y, z, color);
+        } else if z > self.get_depth(x, y) {
+            let i = (x + y * WIDTH as i32) as usize;
+            self.pixels[i] = color.blend(Color::from_u32(self.pixels[i]), mode).to_u32();
+        }
+    }
     pub fn get_pixel(&self, x: i32, y: i32) -> u32 {
         return self.pixels[(x + y * WIDTH as i32) as usize];
     }
-    fn put_depth(&mut self, x: i32, y: i32, depth: f32) {
+    pub fn put_depth(&mut self, x: i32, y: i32, depth: f32) {
         self.depth[(x + y * WIDTH as i32) as usize] = depth;
     }
-    fn get_depth(&self, x: i32, y: i32) -> f32 {
+    pub fn get_depth(&self, x: i32, y: i32) -> f32 {
         return self.depth[(x + y * WIDTH as i32) as usize];
     }
     pub fn viewport_to_canvas(pos: &mut Vec3) {
@@ -43,3 +60,11 @@ pub fn viewport_to_canvas(pos: &mut Vec3) {
         pos.y *= 0.5 * HEIGHT as f32;
     }
 }
+impl RenderTarget<Color> for Canvas {
+    fn canvas(&mut self) -> &mut Canvas {
+        return self;
+    }
+    fn write(&mut self, x: i32, y: i32, z: f32, color: Color, blend: BlendMode) {
+        self.blend_pixel(x, y, z, color, blend);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::canvas::Canvas;
+use crate::mesh::Mesh;
+use crate::renderer::Renderer;
+use crate::scene_file::load_scene;
+use crate::simplify::{simplify, SimplifyTarget};
+use crate::utils::save_to_ppm;
+
+// Subcommands that run without opening a window, None if args don't name one
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    let command = args.get(1)?;
+    match command.as_str() {
+        "render" => Some(render(&args[2..])),
+        "simplify" => Some(simplify_obj(&args[2..])),
+        _ => None,
+    }
+}
+
+// render <scene.json> [output.ppm]
+fn render(args: &[String]) -> Result<(), String> {
+    let Some(path) = args.first() else { return Err("usage: render <scene.json> [output.ppm]".to_string()) };
+    let output = args.get(1).map_or("output.ppm", String::as_str);
+
+    let mut canvas = Canvas::new();
+    let mut renderer = Renderer::new();
+    let mut file = load_scene(path, &mut renderer)?;
+    let camera = file.scene.camera().unwrap_or(file.camera);
+
+    canvas.clear(file.background);
+    renderer.render_scene(&mut file.scene, &camera, &mut canvas);
+    save_to_ppm(output, &canvas.pixels[..]).map_err(|e| format!("{}: {}", output, e))?;
+    println!("Saved {}, culled {}/{} models", output, renderer.cull_stats.culled, renderer.cull_stats.tested);
+    return Ok(());
+}
+
+// simplify <input.obj> <output.obj> [--triangles N] [--error E]
+fn simplify_obj(args: &[String]) -> Result<(), String> {
+    let usage = || "usage: simplify <input.obj> <output.obj> [--triangles N] [--error E]".to_string();
+    let (Some(input), Some(output)) = (args.first(), args.get(1)) else { return Err(usage()) };
+    let mut target = SimplifyTarget { triangles: 0, max_error: f32::INFINITY };
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or_else(usage)?;
+        match option.as_str() {
+            "--triangles" => target.triangles = value.parse().map_err(|_| format!("bad triangle count {}", value))?,
+            "--error" => target.max_error = value.parse().map_err(|_| format!("bad error {}", value))?,
+            _ => return Err(usage()),
+        }
+    }
+    if target.triangles == 0 && target.max_error == f32::INFINITY {
+        return Err("simplify needs --triangles or --error".to_string());
+    }
+
+    let mesh = Mesh::from_obj(input)?;
+    let simplified = simplify(&mesh, target);
+    simplified.save_obj(output).map_err(|e| format!("{}: {}", output, e))?;
+    println!("Saved {}, {} -> {} triangles", output, mesh.triangles.len(), simplified.triangles.len());
+    return Ok(());
+}
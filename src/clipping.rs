@@ -1,8 +1,20 @@
 use glam::Vec3;
 
-use crate::mesh::Vertex;
-use crate::{mesh::Triangle, utils::scale_color};
-use crate::utils::*;
+use crate::mesh::{Triangle, Vertex};
+
+// Anything with a position that can be interpolated can be clipped
+pub trait ClipVertex: Copy {
+    fn pos(&self) -> Vec3;
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+impl ClipVertex for Vertex {
+    fn pos(&self) -> Vec3 {
+        return self.pos;
+    }
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        return Vertex::lerp(self, other, t);
+    }
+}
 
 fn distance_point_plane(p: &Vec3, plane: &Vec3, plane_n: &Vec3) -> f32 {
     return plane_n.x * p.x + plane_n.y * p.y + plane_n.z * p.z - Vec3::dot(*plane_n, *plane);
@@ -21,15 +33,15 @@ fn vector_intersect_plane(plane: &Vec3, plane_n: &Vec3, line_start: &Vec3, line_
 }
 
 // Thank you @Javidx9
-pub fn clip_triangle(tri: &Triangle, plane: &Vec3, plane_n: &Vec3) -> Vec::<Triangle> {
-    let mut result = Vec::<Triangle>::new();
+pub fn clip_triangle<V: ClipVertex>(tri: &Triangle<V>, plane: &Vec3, plane_n: &Vec3) -> Vec::<Triangle<V>> {
+    let mut result = Vec::<Triangle<V>>::new();
 
-    let mut inside_points = Vec::<Vertex>::new();
-    let mut outside_points = Vec::<Vertex>::new();
+    let mut inside_points = Vec::<V>::new();
+    let mut outside_points = Vec::<V>::new();
 
-    let d0 = distance_point_plane(&tri.v[0].pos, &plane, &plane_n) > 0.0;
-    let d1 = distance_point_plane(&tri.v[1].pos, &plane, &plane_n) > 0.0;
-    let d2 = distance_point_plane(&tri.v[2].pos, &plane, &plane_n) > 0.0;
+    let d0 = distance_point_plane(&tri.v[0].pos(), &plane, &plane_n) > 0.0;
+    let d1 = distance_point_plane(&tri.v[1].pos(), &plane, &plane_n) > 0.0;
+    let d2 = distance_point_plane(&tri.v[2].pos(), &plane, &plane_n) > 0.0;
 
     // Checking points
     if d0 {
@@ -54,20 +66,13 @@ pub fn clip_triangle(tri: &Triangle, plane: &Vec3, plane_n: &Vec3) -> Vec::<Tria
         let mut new = tri.clone();
         let mut t = 0.0;
 
-        new.v[0].pos = inside_points[0].pos;
-        new.v[0].color = inside_points[0].color;
-        new.v[0].texture = inside_points[0].texture;
-        new.v[0].lit = inside_points[0].lit;
+        new.v[0] = inside_points[0];
 
-        new.v[1].pos = vector_intersect_plane(plane, plane_n, &inside_points[0].pos, &outside_points[0].pos, &mut t);
-        new.v[1].color = add_colors(scale_color(inside_points[0].color, 1.0-t), scale_color(outside_points[0].color, t));
-        new.v[1].texture = t * (outside_points[0].texture - inside_points[0].texture) + inside_points[0].texture;
-        new.v[1].lit = inside_points[0].lit * (1.0-t) + outside_points[0].lit * t;
+        vector_intersect_plane(plane, plane_n, &inside_points[0].pos(), &outside_points[0].pos(), &mut t);
+        new.v[1] = inside_points[0].lerp(&outside_points[0], t);
 
-        new.v[2].pos = vector_intersect_plane(plane, plane_n, &inside_points[0].pos, &outside_points[1].pos, &mut t);
-        new.v[2].color = add_colors(scale_color(inside_points[0].color, 1.0-t), scale_color(outside_points[1].color, t));
-        new.v[2].texture = t * (outside_points[1].texture - inside_points[0].texture) + inside_points[0].texture;
-        new.v[2].lit = inside_points[0].lit * (1.0-t) + outside_points[1].lit * t;
+        vector_intersect_plane(plane, plane_n, &inside_points[0].pos(), &outside_points[1].pos(), &mut t);
+        new.v[2] = inside_points[0].lerp(&outside_points[1], t);
 
         result.push(new);
     } else if inside_points.len() == 2 && outside_points.len() == 1 {
@@ -75,36 +80,18 @@ pub fn clip_triangle(tri: &Triangle, plane: &Vec3, plane_n: &Vec3) -> Vec::<Tria
         let mut new_1 = tri.clone();
         let mut t = 0.0;
 
-        new_0.v[0].pos = inside_points[0].pos;
-        new_0.v[0].color = inside_points[0].color;
-        new_0.v[0].texture = inside_points[0].texture;
-        new_0.v[0].lit = inside_points[0].lit;
-
-        new_0.v[1].pos = inside_points[1].pos;
-        new_0.v[1].color = inside_points[1].color;
-        new_0.v[1].texture = inside_points[1].texture;
-        new_0.v[1].lit = inside_points[1].lit;
+        new_0.v[0] = inside_points[0];
+        new_0.v[1] = inside_points[1];
 
-        new_0.v[2].pos = vector_intersect_plane(plane, plane_n, &inside_points[0].pos, &outside_points[0].pos, &mut t);
-        new_0.v[2].color = add_colors(scale_color(inside_points[0].color, 1.0-t), scale_color(outside_points[0].color, t));
-        new_0.v[2].texture = t * (outside_points[0].texture - inside_points[0].texture) + inside_points[0].texture;
-        new_0.v[2].lit = inside_points[0].lit * (1.0-t) + outside_points[0].lit * t;
+        vector_intersect_plane(plane, plane_n, &inside_points[0].pos(), &outside_points[0].pos(), &mut t);
+        new_0.v[2] = inside_points[0].lerp(&outside_points[0], t);
 
         // Second triangle
-        new_1.v[0].pos = inside_points[1].pos;
-        new_1.v[0].color = inside_points[1].color;
-        new_1.v[0].texture = inside_points[1].texture;
-        new_1.v[0].lit = inside_points[1].lit;
-
-        new_1.v[1].pos = new_0.v[2].pos;
-        new_1.v[1].color = new_0.v[2].color;
-        new_1.v[1].texture = new_0.v[2].texture;
-        new_1.v[1].lit = new_0.v[2].lit;
-
-        new_1.v[2].pos = vector_intersect_plane(plane, plane_n, &inside_points[1].pos, &outside_points[0].pos, &mut t);
-        new_1.v[2].color = add_colors(scale_color(inside_points[1].color, 1.0-t), scale_color(outside_points[0].color, t));
-        new_1.v[2].texture = t * (outside_points[0].texture - inside_points[1].texture) + inside_points[1].texture;
-        new_1.v[2].lit = inside_points[1].lit * (1.0-t) + outside_points[0].lit * t;
+        new_1.v[0] = inside_points[1];
+        new_1.v[1] = new_0.v[2];
+
+        vector_intersect_plane(plane, plane_n, &inside_points[1].pos(), &outside_points[0].pos(), &mut t);
+        new_1.v[2] = inside_points[1].lerp(&outside_points[0], t);
 
         result.push(new_0);
         result.push(new_1);
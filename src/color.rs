@@ -0,0 +1,104 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlendMode {
+    Opaque,         // Overwrites, the only mode that writes depth
+    Alpha,          // src * a + dst * (1 - a)
+    Additive,       // dst + src * a
+    Multiply,       // src * dst
+    Premultiplied,  // src + dst * (1 - a), src rgb already multiplied by alpha
+}
+
+// Float color, the 0..255 bytes of textures and the canvas divided by 255 with no gamma
+// conversion (so sRGB values, lit as if they were linear), packed into u32 only when written
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+impl Color {
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b, a: 1.0 }
+    }
+    pub const fn splat(v: f32) -> Self {
+        Self::rgb(v, v, v)
+    }
+    // Canvas and texture pixels are 0xAABBGGRR
+    pub fn from_u32(color: u32) -> Self {
+        let [r, g, b, a] = color.to_le_bytes();
+        Self::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+    }
+    pub fn to_u32(self) -> u32 {
+        let c = self.clamp();
+        u32::from_le_bytes([
+            (c.r * 255.0).round() as u8,
+            (c.g * 255.0).round() as u8,
+            (c.b * 255.0).round() as u8,
+            (c.a * 255.0).round() as u8,
+        ])
+    }
+    pub fn clamp(&self) -> Self {
+        Self::new(self.r.clamp(0.0, 1.0), self.g.clamp(0.0, 1.0), self.b.clamp(0.0, 1.0), self.a.clamp(0.0, 1.0))
+    }
+    pub fn lerp(&self, other: Color, t: f32) -> Self {
+        *self + (other - *self) * t
+    }
+    // Scales rgb only, alpha is left untouched
+    pub fn scale_rgb(&self, scale: f32) -> Self {
+        Self::new(self.r * scale, self.g * scale, self.b * scale, self.a)
+    }
+    // Blends self (source) over the destination color
+    pub fn blend(&self, dst: Color, mode: BlendMode) -> Self {
+        let a = self.a;
+        let rgb = match mode {
+            BlendMode::Opaque => return *self,
+            BlendMode::Alpha => self.scale_rgb(a) + dst.scale_rgb(1.0 - a),
+            BlendMode::Additive => dst + self.scale_rgb(a),
+            BlendMode::Multiply => self.mul_rgb(dst),
+            BlendMode::Premultiplied => *self + dst.scale_rgb(1.0 - a),
+        };
+        return Color { a: a + dst.a * (1.0 - a), ..rgb };
+    }
+    // Multiplies rgb only, used to apply light to a surface color
+    pub fn mul_rgb(&self, other: Color) -> Self {
+        Self::new(self.r * other.r, self.g * other.g, self.b * other.b, self.a)
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+    fn add(self, rhs: Color) -> Color {
+        Color::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b, self.a + rhs.a)
+    }
+}
+impl AddAssign for Color {
+    fn add_assign(&mut self, rhs: Color) {
+        *self = *self + rhs;
+    }
+}
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, rhs: Color) -> Color {
+        Color::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b, self.a - rhs.a)
+    }
+}
+impl Mul for Color {
+    type Output = Color;
+    fn mul(self, rhs: Color) -> Color {
+        Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b, self.a * rhs.a)
+    }
+}
+impl Mul<f32> for Color {
+    type Output = Color;
+    fn mul(self, rhs: f32) -> Color {
+        Color::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a * rhs)
+    }
+}
@@ -0,0 +1,78 @@
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+use crate::color::Color;
+use crate::shader::sample;
+use crate::utils::try_load_pixels;
+
+// Faces are in the order +X, -X, +Y, -Y, +Z, -Z and stored like any other texture
+// (flipped by tex.sh, so the first row is the bottom of the image)
+pub struct CubeMap {
+    pub faces: [(Vec<u32>, u32, u32); 6],
+}
+impl CubeMap {
+    pub fn from_faces(paths: [&str; 6]) -> Self {
+        return Self::try_from_faces(paths).unwrap_or_else(|e| panic!("{}", e));
+    }
+    pub fn try_from_faces(paths: [&str; 6]) -> Result<Self, String> {
+        let faces = paths.map(try_load_pixels);
+        if let Some(Err(e)) = faces.iter().find(|f| f.is_err()) {
+            return Err(e.clone());
+        }
+        return Ok(Self { faces: faces.map(Result::unwrap) });
+    }
+    pub fn from_equirectangular(path: &str, size: u32) -> Self {
+        return Self::try_from_equirectangular(path, size).unwrap_or_else(|e| panic!("{}", e));
+    }
+    // Builds size x size faces out of a longitude / latitude panorama
+    pub fn try_from_equirectangular(path: &str, size: u32) -> Result<Self, String> {
+        let panorama = try_load_pixels(path)?;
+        let faces = [0, 1, 2, 3, 4, 5].map(|face| {
+            let mut pixels = Vec::<u32>::with_capacity((size * size) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    // Face uv with the origin at the top left, like the sampling below
+                    let uv = Vec2::new((x as f32 + 0.5) / size as f32, 1.0 - (y as f32 + 0.5) / size as f32);
+                    let dir = face_dir(face, uv);
+                    let u = dir.x.atan2(-dir.z) / (2.0 * PI) + 0.5;
+                    let v = 0.5 - dir.y.clamp(-1.0, 1.0).asin() / PI;
+                    pixels.push(sample(&panorama, Vec2::new(u, 1.0 - v)).to_u32());
+                }
+            }
+            (pixels, size, size)
+        });
+        return Ok(Self { faces });
+    }
+    pub fn sample(&self, dir: Vec3) -> Color {
+        let (face, uv) = dir_face(dir);
+        return sample(&self.faces[face], Vec2::new(uv.x, 1.0 - uv.y));
+    }
+}
+
+// Direction -> face and uv (origin top left), same layout as OpenGL cube maps
+fn dir_face(dir: Vec3) -> (usize, Vec2) {
+    let a = dir.abs();
+    let (face, sc, tc, ma) = if a.x >= a.y && a.x >= a.z {
+        if dir.x > 0.0 { (0, -dir.z, -dir.y, a.x) } else { (1, dir.z, -dir.y, a.x) }
+    } else if a.y >= a.z {
+        if dir.y > 0.0 { (2, dir.x, dir.z, a.y) } else { (3, dir.x, -dir.z, a.y) }
+    } else {
+        if dir.z > 0.0 { (4, dir.x, -dir.y, a.z) } else { (5, -dir.x, -dir.y, a.z) }
+    };
+    let uv = (Vec2::new(sc, tc) / ma + Vec2::ONE) * 0.5;
+    return (face, uv.clamp(Vec2::ZERO, Vec2::ONE));
+}
+
+// Inverse of dir_face
+fn face_dir(face: usize, uv: Vec2) -> Vec3 {
+    let (s, t) = (uv.x * 2.0 - 1.0, uv.y * 2.0 - 1.0);
+    let dir = match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    };
+    return dir.normalize();
+}
@@ -0,0 +1,62 @@
+use crate::color::Color;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FogMode {
+    Off,
+    Linear,     // Fades from start to end
+    Exp,        // e^-(density * depth)
+    Exp2,       // e^-(density * depth)^2
+}
+
+// Fog that gets thicker below base, falloff controls how fast it thins out above it
+#[derive(Clone, Copy, Debug)]
+pub struct HeightFog {
+    pub base: f32,
+    pub falloff: f32,
+    pub density: f32,
+}
+
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Color,
+    pub density: f32,
+    pub start: f32,
+    pub end: f32,
+    pub height: Option<HeightFog>,
+}
+impl Fog {
+    pub fn new(color: Color) -> Self {
+        Self {
+            mode: FogMode::Off,
+            color,
+            density: 0.15,
+            start: 1.0,
+            end: 10.0,
+            height: None,
+        }
+    }
+    // How much of the surface is still visible (1.0 = no fog) at a view space depth and world height
+    pub fn visibility(&self, depth: f32, height: f32) -> f32 {
+        let mut visibility = match self.mode {
+            FogMode::Off => 1.0,
+            // end <= start is a hard cutoff at end
+            FogMode::Linear if self.end <= self.start => if depth < self.end { 1.0 } else { 0.0 },
+            FogMode::Linear => ((self.end - depth) / (self.end - self.start)).clamp(0.0, 1.0),
+            FogMode::Exp => (-self.density * depth).exp(),
+            FogMode::Exp2 => (-(self.density * depth).powi(2)).exp(),
+        };
+        if let Some(h) = self.height {
+            let thickness = (-h.falloff * (height - h.base)).exp().min(1.0);
+            visibility *= (-h.density * thickness * depth).exp();
+        }
+        return visibility;
+    }
+    pub fn apply(&self, color: Color, depth: f32, height: f32) -> Color {
+        if self.mode == FogMode::Off && self.height.is_none() {
+            return color;
+        }
+        let visibility = self.visibility(depth, height);
+        let fogged = self.color.lerp(color, visibility);
+        return Color { a: color.a, ..fogged };
+    }
+}
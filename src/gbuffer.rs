@@ -0,0 +1,143 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::camera::Projection;
+use crate::canvas::{Canvas, RenderTarget, HEIGHT, WIDTH};
+use crate::color::{BlendMode, Color};
+use crate::fog::Fog;
+use crate::lighting::{Light, LightKind, AMBIENT};
+use crate::shader::{albedo, surface_normal, DefaultUniforms, DefaultVaryings, FragmentShader};
+
+// Surface data for deferred shading, depth comes from the canvas it's drawn alongside
+pub struct GBuffer {
+    pub albedo: Box<[u32]>,
+    pub normal: Box<[Vec3]>,
+    pub material: Box<[u16]>,   // 0 = nothing drawn
+    world: Box<[Vec3]>,         // Rebuilt from depth by the lighting pass
+    light: Box<[Color]>,        // Light accumulated by the lighting pass
+}
+impl GBuffer {
+    pub fn new() -> Self {
+        Self {
+            albedo: vec![0u32; WIDTH * HEIGHT].into_boxed_slice(),
+            normal: vec![Vec3::ZERO; WIDTH * HEIGHT].into_boxed_slice(),
+            material: vec![0u16; WIDTH * HEIGHT].into_boxed_slice(),
+            world: vec![Vec3::ZERO; WIDTH * HEIGHT].into_boxed_slice(),
+            light: vec![Color::BLACK; WIDTH * HEIGHT].into_boxed_slice(),
+        }
+    }
+    pub fn clear(&mut self) {
+        self.albedo.fill(0);
+        self.normal.fill(Vec3::ZERO);
+        self.material.fill(0);
+    }
+    // Full screen lighting pass, writes the lit result into the canvas pixels
+    // Lights with a range only touch the pixels their sphere covers on screen
+    pub fn light(&mut self, canvas: &mut Canvas, lights: &[Light], fog: &Fog, mat_view: Mat4, projection: &Projection) {
+        let inv_view = mat_view.inverse();
+        let inv_proj = projection.matrix().inverse();
+
+        for i in 0..WIDTH * HEIGHT {
+            if self.material[i] == 0 {
+                continue;
+            }
+            let (x, y) = ((i % WIDTH) as f32, (i / WIDTH) as f32);
+            let ndc = Vec3::new(x / (0.5 * WIDTH as f32) - 1.0, y / (0.5 * HEIGHT as f32) - 1.0, canvas.depth[i]);
+            self.world[i] = inv_view.transform_point3(inv_proj.project_point3(ndc));
+            self.light[i] = AMBIENT;
+        }
+
+        for light in lights.iter() {
+            let (min, max) = light_rect(light, mat_view, projection);
+            for y in min.1..=max.1 {
+                for x in min.0..=max.0 {
+                    let i = x as usize + y as usize * WIDTH;
+                    if self.material[i] == 0 {
+                        continue;
+                    }
+                    self.light[i] += light.radiance_shadowed(self.normal[i], self.world[i]);
+                }
+            }
+        }
+
+        let view_z = mat_view.row(2);
+        for i in 0..WIDTH * HEIGHT {
+            if self.material[i] == 0 {
+                continue;
+            }
+            let color = Color::from_u32(self.albedo[i]).mul_rgb(self.light[i]);
+            let world = self.world[i];
+            let depth = view_z.dot(world.extend(1.0)).abs();
+            canvas.pixels[i] = fog.apply(color, depth, world.y).to_u32();
+        }
+    }
+}
+
+// Screen rectangle (min, max) a light can reach
+fn light_rect(light: &Light, mat_view: Mat4, projection: &Projection) -> ((i32, i32), (i32, i32)) {
+    let full = ((0, 0), (WIDTH as i32 - 1, HEIGHT as i32 - 1));
+    let range = match (light.kind, light.range) {
+        (LightKind::Directional(_), _) | (_, None) => return full,
+        (_, Some(range)) => range,
+    };
+
+    // Project the corners of the box around the light sphere
+    let center = mat_view.transform_point3(light.pos);
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for corner in 0..8 {
+        let offset = Vec3::new(
+            if corner & 1 == 0 { -range } else { range },
+            if corner & 2 == 0 { -range } else { range },
+            if corner & 4 == 0 { -range } else { range },
+        );
+        let p = center + offset;
+        if p.z <= projection.near() {
+            // Crosses the near plane, not worth the trouble
+            return full;
+        }
+        let mut p = projection.matrix().project_point3(p);
+        Canvas::viewport_to_canvas(&mut p);
+        min = min.min(p.truncate());
+        max = max.max(p.truncate());
+    }
+    let min = min.clamp(Vec2::ZERO, Vec2::new(WIDTH as f32 - 1.0, HEIGHT as f32 - 1.0));
+    let max = max.clamp(Vec2::ZERO, Vec2::new(WIDTH as f32 - 1.0, HEIGHT as f32 - 1.0));
+    return ((min.x as i32, min.y as i32), (max.x.ceil() as i32, max.y.ceil() as i32));
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GSample {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub material: u16,
+}
+
+// Geometry pass target, deferred geometry is always opaque
+pub struct GBufferTarget<'a> {
+    pub gbuffer: &'a mut GBuffer,
+    pub canvas: &'a mut Canvas,
+}
+impl RenderTarget<GSample> for GBufferTarget<'_> {
+    fn canvas(&mut self) -> &mut Canvas {
+        return self.canvas;
+    }
+    fn write(&mut self, x: i32, y: i32, z: f32, out: GSample, _: BlendMode) {
+        if z > self.canvas.get_depth(x, y) {
+            let i = (x + y * WIDTH as i32) as usize;
+            self.canvas.put_depth(x, y, z);
+            self.gbuffer.albedo[i] = out.albedo.to_u32();
+            self.gbuffer.normal[i] = out.normal;
+            self.gbuffer.material[i] = out.material;
+        }
+    }
+}
+
+// Same inputs as the default fragment shader, but stores the surface instead of lighting it
+pub struct GBufferShader;
+impl<'a> FragmentShader<DefaultUniforms<'a>> for GBufferShader {
+    type Varyings = DefaultVaryings;
+    type Output = GSample;
+    fn fragment(&self, v: &DefaultVaryings, u: &DefaultUniforms<'a>) -> Option<GSample> {
+        return Some(GSample { albedo: albedo(v, u)?, normal: surface_normal(v, u), material: u.material_id });
+    }
+}
@@ -0,0 +1,511 @@
+use glam::{Vec2, Vec3, Vec4, Quat, Mat3, Mat4};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::animation::{Clip, Interpolation, Keyframe, Track};
+use crate::color::Color;
+use crate::json::Json;
+use crate::mesh::{Mesh, MorphDelta, MorphTarget, Triangle, Vertex};
+use crate::skeleton::{Joint, Skeleton, SkeletonClip};
+
+// Every triangle mesh in a .gltf or .glb file merged into one Mesh, rigid meshes are placed
+// by their node. Meshes using the first skin keep their joints and weights and the skin's
+// animations become the mesh's clips. Morph targets are matched by name across meshes (extras.targetNames)
+// and are zero on triangles that don't have them. Images are ignored, textures are loaded like any other.
+pub fn load_gltf(path: &str) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    return Gltf::parse(path, &bytes).and_then(|gltf| gltf.mesh()).map_err(|e| format!("{}: {}", path, e));
+}
+
+struct Gltf {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    parents: Vec<Option<usize>>,
+}
+impl Gltf {
+    fn parse(path: &str, bytes: &[u8]) -> Result<Self, String> {
+        // Binary files are a JSON chunk followed by a BIN chunk
+        let (text, mut bin) = if bytes.starts_with(b"glTF") {
+            let chunk = |offset: usize| -> Result<(u32, &[u8]), String> {
+                let header = bytes.get(offset..offset + 8).ok_or("truncated chunk header")?;
+                let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+                let kind = u32::from_le_bytes(header[4..8].try_into().unwrap());
+                return Ok((kind, bytes.get(offset + 8..offset + 8 + length).ok_or("truncated chunk")?));
+            };
+            let (kind, json) = chunk(12)?;
+            if kind != 0x4E4F534A {
+                return Err("first chunk isn't JSON".to_string());
+            }
+            let bin = chunk(20 + json.len()).ok().filter(|(kind, _)| *kind == 0x004E4942).map(|(_, b)| b.to_vec());
+            (json, bin)
+        } else {
+            (bytes, None)
+        };
+        let text = std::str::from_utf8(text).map_err(|_| "JSON isn't valid UTF-8".to_string())?;
+        let json = Json::parse(text)?;
+
+        let mut buffers = Vec::new();
+        for (i, buffer) in array(&json, "buffers").iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Json::as_str) {
+                None => bin.take().ok_or(format!("buffer {}: no uri and no binary chunk", i))?,
+                Some(uri) if uri.starts_with("data:") => {
+                    let data = uri.split_once(";base64,").ok_or(format!("buffer {}: only base64 data uris are supported", i))?.1;
+                    base64(data).ok_or(format!("buffer {}: bad base64", i))?
+                }
+                Some(uri) => {
+                    let file = Path::new(path).with_file_name(uri);
+                    fs::read(&file).map_err(|e| format!("buffer {}: {}: {}", i, file.display(), e))?
+                }
+            };
+            buffers.push(data);
+        }
+
+        let nodes = array(&json, "nodes");
+        let mut parents = vec![None; nodes.len()];
+        for (i, node) in nodes.iter().enumerate() {
+            for child in array(node, "children").iter().filter_map(Json::as_usize) {
+                let parent = parents.get_mut(child).ok_or(format!("node {}: child {} doesn't exist", i, child))?;
+                if parent.is_some() {
+                    return Err(format!("node {}: cycle in children", child));
+                }
+                *parent = Some(i);
+            }
+        }
+        // With one parent each, a cycle is a node that gets back to itself within nodes.len() steps
+        for i in 0..nodes.len() {
+            let mut node = parents[i];
+            for _ in 0..nodes.len() {
+                let Some(p) = node else { break };
+                if p == i {
+                    return Err(format!("node {}: cycle in children", i));
+                }
+                node = parents[p];
+            }
+        }
+        return Ok(Self { json, buffers, parents });
+    }
+
+    fn mesh(&self) -> Result<Mesh, String> {
+        let nodes = array(&self.json, "nodes");
+        let skin = nodes.iter().find_map(|n| n.get("mesh").and(n.get("skin")).and_then(Json::as_usize));
+
+        let mut mesh = Mesh::new();
+        for (i, node) in nodes.iter().enumerate() {
+            let Some(index) = node.get("mesh").and_then(Json::as_usize) else { continue };
+            let skinned = skin.is_some() && node.get("skin").and_then(Json::as_usize) == skin;
+            // Skinned meshes are placed by their joints, the node transform is ignored
+            let mat = if skinned { Mat4::IDENTITY } else { self.global(i) };
+            self.primitives(index, mat, skinned, &mut mesh).map_err(|e| format!("mesh {}: {}", index, e))?;
+        }
+        if mesh.triangles.is_empty() {
+            return Err("no triangles in any mesh".to_string());
+        }
+        pad_targets(&mut mesh);
+        if let Some(skin) = skin {
+            mesh.skeleton = Some(self.skeleton(skin).map_err(|e| format!("skin {}: {}", skin, e))?);
+            mesh.clips = self.clips(skin)?;
+        }
+
+        if mesh.triangles.iter().any(|t| t.v[0].texture != Vec2::new(-1.0, -1.0)) {
+            mesh.generate_tangents();
+        }
+        mesh.compute_bounds();
+        mesh.build_bvh();
+        return Ok(mesh);
+    }
+
+    fn primitives(&self, index: usize, mat: Mat4, skinned: bool, out: &mut Mesh) -> Result<(), String> {
+        let mesh = array(&self.json, "meshes").get(index).ok_or("doesn't exist")?;
+        let mat_normal = Mat3::from_mat4(mat).inverse().transpose();
+        let target_names = mesh.get("extras").map_or(&[][..], |e| array(e, "targetNames"));
+        let default_weights = mesh.get("weights").and_then(Json::as_floats).unwrap_or_default();
+        for (p, primitive) in array(mesh, "primitives").iter().enumerate() {
+            let error = |e: String| format!("primitive {}: {}", p, e);
+            // Only plain triangle lists, points, lines and strips are skipped
+            if primitive.get("mode").and_then(Json::as_usize).unwrap_or(4) != 4 {
+                continue;
+            }
+            let attribute = |name: &str, types: &[&str]| -> Result<Option<(Vec<f32>, usize)>, String> {
+                let Some(a) = primitive.get("attributes").and_then(|a| a.get(name)) else { return Ok(None) };
+                return self.accessor(a, types).map(Some).map_err(|e| format!("{}: {}", name, e));
+            };
+            let (pos, _) = attribute("POSITION", &["VEC3"]).map_err(error)?.ok_or("POSITION is missing".to_string()).map_err(error)?;
+            let count = pos.len() / 3;
+            let normals = attribute("NORMAL", &["VEC3"]).map_err(error)?;
+            let uvs = attribute("TEXCOORD_0", &["VEC2"]).map_err(error)?;
+            let colors = attribute("COLOR_0", &["VEC3", "VEC4"]).map_err(error)?;
+            let (joints, weights) = if skinned {
+                (attribute("JOINTS_0", &["VEC4"]).map_err(error)?, attribute("WEIGHTS_0", &["VEC4"]).map_err(error)?)
+            } else {
+                (None, None)
+            };
+            let indices: Vec<usize> = match primitive.get("indices") {
+                Some(i) => self.accessor(i, &["SCALAR"]).map_err(|e| error(format!("indices: {}", e)))?.0.iter().map(|i| *i as usize).collect(),
+                None => (0..count).collect(),
+            };
+
+            let vertex = |i: usize| -> Result<Vertex, String> {
+                if i >= count {
+                    return Err(format!("index {} out of range", i));
+                }
+                let mut v = Vertex::new(
+                    mat.transform_point3(Vec3::from_slice(&pos[i * 3..i * 3 + 3])),
+                    element(&normals, i).map_or(Vec3::ZERO, |n| (mat_normal * Vec3::from_slice(n)).normalize_or_zero()),
+                    element(&uvs, i).map_or(Vec2::new(-1.0, -1.0), |t| Vec2::new(t[0], 1.0 - t[1])),
+                    element(&colors, i).map_or(Color::WHITE, |c| Color::new(c[0], c[1], c[2], c.get(3).copied().unwrap_or(1.0))),
+                );
+                if let (Some(j), Some(w)) = (element(&joints, i), element(&weights, i)) {
+                    v.joints = [j[0] as u16, j[1] as u16, j[2] as u16, j[3] as u16];
+                    let w = Vec4::from_slice(w);
+                    v.weights = w / w.dot(Vec4::ONE).max(f32::EPSILON);
+                }
+                return Ok(v);
+            };
+
+            // Targets of earlier primitives get zero deltas for the triangles this one adds
+            pad_targets(out);
+            for (t, target) in array(primitive, "targets").iter().enumerate() {
+                let attribute = |name: &str| -> Result<Option<(Vec<f32>, usize)>, String> {
+                    let Some(a) = target.get(name) else { return Ok(None) };
+                    let (values, n) = self.accessor(a, &["VEC3"]).map_err(|e| format!("target {} {}: {}", t, name, e))?;
+                    if values.len() != count * n {
+                        return Err(format!("target {} {}: {} elements for {} vertices", t, name, values.len() / n, count));
+                    }
+                    return Ok(Some((values, n)));
+                };
+                let (positions, target_normals) = (attribute("POSITION").map_err(error)?, attribute("NORMAL").map_err(error)?);
+                let name = target_names.get(t).and_then(Json::as_str).map_or(format!("{}.{}", index, t), str::to_string);
+                let slot = match out.morph_target(&name) {
+                    Some(slot) => slot,
+                    None => {
+                        let weight = default_weights.get(t).copied().unwrap_or(0.0);
+                        out.morph_targets.push(MorphTarget::new(&name, weight, out.triangles.len()));
+                        out.morph_targets.len() - 1
+                    }
+                };
+                let delta = |i: usize| MorphDelta {
+                    pos: element(&positions, i).map_or(Vec3::ZERO, |p| mat.transform_vector3(Vec3::from_slice(p))),
+                    normal: element(&target_normals, i).filter(|_| normals.is_some()).map_or(Vec3::ZERO, |n| mat_normal * Vec3::from_slice(n)),
+                };
+                for tri in indices.chunks_exact(3) {
+                    out.morph_targets[slot].deltas.push(Triangle::new(delta(tri[0]), delta(tri[1]), delta(tri[2])));
+                }
+            }
+
+            for tri in indices.chunks_exact(3) {
+                let mut v = [vertex(tri[0]).map_err(error)?, vertex(tri[1]).map_err(error)?, vertex(tri[2]).map_err(error)?];
+                // Flat normals when there are none
+                if normals.is_none() {
+                    let n = (v[1].pos - v[0].pos).cross(v[2].pos - v[0].pos).normalize_or_zero();
+                    v.iter_mut().for_each(|v| v.normal = n);
+                }
+                out.triangles.push(Triangle::new(v[0], v[1], v[2]));
+            }
+        }
+        return Ok(());
+    }
+
+    fn skeleton(&self, skin: usize) -> Result<Skeleton, String> {
+        let skin = array(&self.json, "skins").get(skin).ok_or("doesn't exist")?;
+        let nodes = array(&self.json, "nodes");
+        let joint_nodes: Vec<usize> = array(skin, "joints").iter().filter_map(Json::as_usize).collect();
+        let inverse_binds = match skin.get("inverseBindMatrices") {
+            Some(a) => self.accessor(a, &["MAT4"]).map_err(|e| format!("inverseBindMatrices: {}", e))?.0,
+            None => Vec::new(),
+        };
+
+        let mut joints = Vec::new();
+        let mut root = None;
+        for (i, &n) in joint_nodes.iter().enumerate() {
+            let node = nodes.get(n).ok_or(format!("joint {} is missing node {}", i, n))?;
+            // Closest ancestor that is also a joint
+            let mut parent = self.parents[n];
+            while let Some(p) = parent.filter(|p| !joint_nodes.contains(p)) {
+                parent = self.parents[p];
+            }
+            if parent.is_none() && root.is_none() {
+                root = Some(self.parents[n].map_or(Mat4::IDENTITY, |p| self.global(p)));
+            }
+            let (scale, rotation, translation) = local(node).to_scale_rotation_translation();
+            joints.push(Joint {
+                name: node.get("name").and_then(Json::as_str).map_or(format!("joint {}", i), str::to_string),
+                parent: parent.and_then(|p| joint_nodes.iter().position(|j| *j == p)),
+                inverse_bind: inverse_binds.get(i * 16..i * 16 + 16).map_or(Mat4::IDENTITY, Mat4::from_cols_slice),
+                translation,
+                rotation,
+                scale,
+            });
+        }
+        return Ok(Skeleton { joints, root: root.unwrap_or(Mat4::IDENTITY) });
+    }
+
+    // Channels that animate the skin's joints, others are skipped
+    fn clips(&self, skin: usize) -> Result<Vec<Rc<SkeletonClip>>, String> {
+        let skin = &array(&self.json, "skins")[skin];
+        let joint_nodes: Vec<usize> = array(skin, "joints").iter().filter_map(Json::as_usize).collect();
+        let mut clips = Vec::new();
+        for (a, animation) in array(&self.json, "animations").iter().enumerate() {
+            let error = |e: String| format!("animation {}: {}", a, e);
+            let samplers = array(animation, "samplers");
+            let mut tracks: BTreeMap<usize, (Track<Vec3>, Track<Quat>, Track<Vec3>)> = BTreeMap::new();
+            for (c, channel) in array(animation, "channels").iter().enumerate() {
+                let error = |e: String| error(format!("channel {}: {}", c, e));
+                let target = channel.get("target").ok_or("target is missing".to_string()).map_err(error)?;
+                let Some(node) = target.get("node").and_then(Json::as_usize) else { continue };
+                let Some(joint) = joint_nodes.iter().position(|j| *j == node) else { continue };
+                let (path, kind) = match target.get("path").and_then(Json::as_str) {
+                    Some(path @ ("translation" | "scale")) => (path, "VEC3"),
+                    Some("rotation") => ("rotation", "VEC4"),
+                    _ => continue,
+                };
+                let sampler = channel.get("sampler").and_then(Json::as_usize).and_then(|s| samplers.get(s))
+                    .ok_or("bad sampler".to_string()).map_err(error)?;
+                let interpolation = match sampler.get("interpolation").map_or(Some("LINEAR"), Json::as_str) {
+                    Some("STEP") => Interpolation::Step,
+                    Some("LINEAR") => Interpolation::Linear,
+                    Some("CUBICSPLINE") => Interpolation::Cubic,
+                    _ => return Err(error("unknown interpolation".to_string())),
+                };
+                let input = sampler.get("input").ok_or("input is missing".to_string()).map_err(error)?;
+                let output = sampler.get("output").ok_or("output is missing".to_string()).map_err(error)?;
+                let times = self.accessor(input, &["SCALAR"]).map_err(|e| error(format!("input: {}", e)))?.0;
+                let (values, n) = self.accessor(output, &[kind]).map_err(|e| error(format!("output: {}", e)))?;
+
+                let entry = tracks.entry(joint).or_insert_with(|| (Track::empty(), Track::empty(), Track::empty()));
+                match path {
+                    "translation" => entry.0 = Track::new(keys(&times, &values, n, interpolation, Vec3::from_slice).map_err(error)?, interpolation),
+                    "rotation" => entry.1 = Track::new(keys(&times, &values, n, interpolation, |v| Quat::from_slice(v).normalize()).map_err(error)?, interpolation),
+                    _ => entry.2 = Track::new(keys(&times, &values, n, interpolation, Vec3::from_slice).map_err(error)?, interpolation),
+                }
+            }
+
+            let joints: Vec<(usize, Clip)> = tracks.into_iter()
+                .map(|(j, (t, r, s))| (j, Clip::new(&format!("joint {}", j)).with_tracks(t, r, s)))
+                .collect();
+            if joints.is_empty() {
+                continue;
+            }
+            clips.push(Rc::new(SkeletonClip {
+                name: animation.get("name").and_then(Json::as_str).map_or(format!("animation {}", a), str::to_string),
+                duration: joints.iter().map(|(_, c)| c.duration).fold(0.0, f32::max),
+                joints,
+            }));
+        }
+        return Ok(clips);
+    }
+
+    fn global(&self, node: usize) -> Mat4 {
+        let parent = self.parents[node].map_or(Mat4::IDENTITY, |p| self.global(p));
+        return parent * array(&self.json, "nodes").get(node).map_or(Mat4::IDENTITY, local);
+    }
+
+    // Elements as floats, normalized integers are mapped to 0..1 (or -1..1)
+    // Types are the element types the caller can use ("VEC3"), anything else is an error
+    fn accessor(&self, json: &Json, types: &[&str]) -> Result<(Vec<f32>, usize), String> {
+        let index = json.as_usize().ok_or("expected an accessor index")?;
+        let accessor = array(&self.json, "accessors").get(index).ok_or(format!("accessor {} doesn't exist", index))?;
+        let error = |e: &str| format!("accessor {}: {}", index, e);
+        if accessor.get("sparse").is_some() {
+            return Err(error("sparse accessors aren't supported"));
+        }
+        let count = accessor.get("count").and_then(Json::as_usize).ok_or_else(|| error("count is missing"))?;
+        let kind = accessor.get("type").and_then(Json::as_str).unwrap_or("no type");
+        let n = match kind {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT4" => 16,
+            _ => return Err(error("unsupported type")),
+        };
+        if !types.contains(&kind) {
+            return Err(error(&format!("{} where {} is expected", kind, types.join(" or "))));
+        }
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+        let (size, read): (usize, fn(&[u8], bool) -> f32) = match accessor.get("componentType").and_then(Json::as_usize) {
+            Some(5120) => (1, |b, norm| if norm { (b[0] as i8 as f32 / 127.0).max(-1.0) } else { b[0] as i8 as f32 }),
+            Some(5121) => (1, |b, norm| if norm { b[0] as f32 / 255.0 } else { b[0] as f32 }),
+            Some(5122) => (2, |b, norm| {
+                let v = i16::from_le_bytes([b[0], b[1]]) as f32;
+                if norm { (v / 32767.0).max(-1.0) } else { v }
+            }),
+            Some(5123) => (2, |b, norm| {
+                let v = u16::from_le_bytes([b[0], b[1]]) as f32;
+                if norm { v / 65535.0 } else { v }
+            }),
+            Some(5125) => (4, |b, _| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+            Some(5126) => (4, |b, _| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            _ => return Err(error("unsupported componentType")),
+        };
+
+        // No buffer view means all zeros
+        let Some(view) = accessor.get("bufferView").and_then(Json::as_usize) else { return Ok((vec![0.0; count * n], n)) };
+        let view = array(&self.json, "bufferViews").get(view).ok_or_else(|| error("bufferView doesn't exist"))?;
+        let buffer = view.get("buffer").and_then(Json::as_usize).and_then(|b| self.buffers.get(b)).ok_or_else(|| error("buffer doesn't exist"))?;
+        let start = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0) + accessor.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let stride = view.get("byteStride").and_then(Json::as_usize).unwrap_or(size * n);
+        if count > 0 && start + stride * (count - 1) + size * n > buffer.len() {
+            return Err(error("reads past the end of its buffer"));
+        }
+
+        let mut values = Vec::with_capacity(count * n);
+        for i in 0..count {
+            for c in 0..n {
+                let at = start + i * stride + c * size;
+                values.push(read(&buffer[at..at + size], normalized));
+            }
+        }
+        return Ok((values, n));
+    }
+}
+
+// Cubic spline outputs are [in tangent, value, out tangent] for every key
+fn keys<T: Copy>(times: &[f32], values: &[f32], n: usize, interpolation: Interpolation, value: impl Fn(&[f32]) -> T) -> Result<Vec<Keyframe<T>>, String> {
+    let per_key = if interpolation == Interpolation::Cubic { 3 } else { 1 };
+    if values.len() < times.len() * per_key * n {
+        return Err("fewer outputs than keyframes".to_string());
+    }
+    let at = |k: usize, i: usize| value(&values[(k * per_key + i) * n..(k * per_key + i + 1) * n]);
+    return Ok(times.iter().enumerate().map(|(k, &time)| {
+        if per_key == 3 {
+            Keyframe { time, value: at(k, 1), tangents: Some((at(k, 0), at(k, 2))) }
+        } else {
+            Keyframe::new(time, at(k, 0))
+        }
+    }).collect());
+}
+
+// Zero deltas for triangles added since a target was last extended
+fn pad_targets(mesh: &mut Mesh) {
+    let zero = Triangle::new(MorphDelta::default(), MorphDelta::default(), MorphDelta::default());
+    for target in mesh.morph_targets.iter_mut() {
+        target.deltas.resize(mesh.triangles.len(), zero.clone());
+    }
+}
+
+// Element i of an optional attribute
+fn element(attribute: &Option<(Vec<f32>, usize)>, i: usize) -> Option<&[f32]> {
+    let (values, n) = attribute.as_ref()?;
+    return values.get(i * n..i * n + n);
+}
+
+fn local(node: &Json) -> Mat4 {
+    if let Some(m) = node.get("matrix").and_then(Json::as_floats).filter(|m| m.len() == 16) {
+        return Mat4::from_cols_slice(&m);
+    }
+    let t = node.get("translation").and_then(Json::as_floats).filter(|t| t.len() == 3).map_or(Vec3::ZERO, |t| Vec3::from_slice(&t));
+    let r = node.get("rotation").and_then(Json::as_floats).filter(|r| r.len() == 4).map_or(Quat::IDENTITY, |r| Quat::from_slice(&r));
+    let s = node.get("scale").and_then(Json::as_floats).filter(|s| s.len() == 3).map_or(Vec3::ONE, |s| Vec3::from_slice(&s));
+    return Mat4::from_scale_rotation_translation(s, r, t);
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    return json.get(key).and_then(Json::as_array).unwrap_or(&[]);
+}
+
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | v as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    return Some(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Accessors without a bufferView read as zeros, enough to build files without buffers
+    fn load(json: &str) -> Result<Mesh, String> {
+        return Gltf::parse("test.gltf", json.as_bytes()).and_then(|gltf| gltf.mesh());
+    }
+    fn error(json: &str) -> String {
+        return load(json).err().expect("loaded");
+    }
+    fn triangle(attributes: &str, accessors: &str) -> String {
+        return format!(r#"{{
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 {} }} }}] }}],
+            "accessors": [{{ "count": 3, "type": "VEC3", "componentType": 5126 }} {}]
+        }}"#, attributes, accessors);
+    }
+
+    #[test]
+    fn loads_a_triangle() {
+        let mesh = load(&triangle(r#", "TEXCOORD_0": 1"#, r#", { "count": 3, "type": "VEC2", "componentType": 5126 }"#)).unwrap();
+        assert_eq!(mesh.triangles.len(), 1);
+        assert_eq!(mesh.triangles[0].v[0].texture, Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn loads_the_skinned_tube() {
+        let mesh = load_gltf("models/tube.gltf").unwrap();
+        assert!(!mesh.triangles.is_empty());
+        assert!(mesh.skeleton.as_ref().is_some_and(|s| !s.joints.is_empty()));
+    }
+
+    #[test]
+    fn rejects_attributes_of_the_wrong_type() {
+        for (name, kind) in [("TEXCOORD_0", "SCALAR"), ("NORMAL", "VEC2"), ("COLOR_0", "SCALAR")] {
+            let json = triangle(&format!(r#", "{}": 1"#, name), &format!(r#", {{ "count": 3, "type": "{}", "componentType": 5126 }}"#, kind));
+            let error = error(&json);
+            assert!(error.contains(name) && error.contains("is expected"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn reads_morph_targets_and_rejects_bad_ones() {
+        let targets = |accessor: &str| triangle("", &format!(", {}", accessor))
+            .replace(r#"} }] }]"#, r#"}, "targets": [{ "POSITION": 1 }] }], "extras": { "targetNames": ["smile"] } }]"#);
+        let mesh = load(&targets(r#"{ "count": 3, "type": "VEC3", "componentType": 5126 }"#)).unwrap();
+        assert_eq!(mesh.morph_target("smile"), Some(0));
+        assert_eq!(mesh.morph_targets[0].deltas.len(), mesh.triangles.len());
+        assert!(error(&targets(r#"{ "count": 3, "type": "VEC2", "componentType": 5126 }"#)).contains("VEC2 where VEC3 is expected"));
+        assert!(error(&targets(r#"{ "count": 2, "type": "VEC3", "componentType": 5126 }"#)).contains("2 elements for 3 vertices"));
+    }
+
+    #[test]
+    fn rejects_animation_outputs_of_the_wrong_type() {
+        let json = r#"{
+            "nodes": [{ "mesh": 0, "skin": 0 }, { "name": "bone" }],
+            "skins": [{ "joints": [1] }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "animations": [{
+                "samplers": [{ "input": 1, "output": 2 }],
+                "channels": [{ "sampler": 0, "target": { "node": 1, "path": "rotation" } }]
+            }],
+            "accessors": [
+                { "count": 3, "type": "VEC3", "componentType": 5126 },
+                { "count": 2, "type": "SCALAR", "componentType": 5126 },
+                { "count": 2, "type": "VEC3", "componentType": 5126 }
+            ]
+        }"#;
+        let error = error(json);
+        assert!(error.contains("output") && error.contains("VEC3 where VEC4 is expected"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_cycles_and_missing_data() {
+        let cycle = r#"{ "nodes": [{ "children": [1] }, { "children": [0] }] }"#;
+        assert!(error(cycle).contains("cycle in children"));
+        assert!(error(&triangle("", "").replace(r#""POSITION": 0"#, r#""NORMAL": 0"#)).contains("POSITION is missing"));
+        assert!(error(&triangle(r#", "NORMAL": 3"#, "")).contains("accessor 3 doesn't exist"));
+        assert!(load("{ \"nodes\": [").is_err());
+    }
+}
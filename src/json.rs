@@ -0,0 +1,244 @@
+#![allow(dead_code)]
+// Minimal JSON reader, enough for scene files and glTF
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),    // Keeps the file order
+}
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { chars: text.chars().collect(), i: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.i < parser.chars.len() {
+            return Err(parser.error("unexpected text after the value"));
+        }
+        return Ok(value);
+    }
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Json::Number(n) => Some(*n as f32),
+            _ => None,
+        }
+    }
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+    // Array of numbers, None if anything else is in it
+    pub fn as_floats(&self) -> Option<Vec<f32>> {
+        return self.as_array()?.iter().map(Json::as_f32).collect();
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    i: usize,
+}
+impl Parser {
+    fn error(&self, what: &str) -> String {
+        let before = &self.chars[..self.i.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let col = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        return format!("{}:{}: {}", line, col, what);
+    }
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.i).copied();
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.i += 1;
+            } else if c == '/' && self.chars.get(self.i + 1) == Some(&'/') {
+                // Line comments, not JSON but handy in hand written scene files
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.i += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c)));
+        }
+        self.i += 1;
+        return Ok(());
+    }
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        let end = self.i + word.len();
+        if end > self.chars.len() || self.chars[self.i..end].iter().collect::<String>() != word {
+            return Err(self.error("expected a value"));
+        }
+        self.i = end;
+        return Ok(value);
+    }
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.i;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.i += 1;
+        }
+        let text: String = self.chars[start..self.i].iter().collect();
+        return text.parse::<f64>().map(Json::Number).map_err(|_| self.error(&format!("bad number '{}'", text)));
+    }
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.peek() else { return Err(self.error("unterminated string")) };
+            self.i += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(e) = self.peek() else { return Err(self.error("unterminated string")) };
+                    self.i += 1;
+                    match e {
+                        'n' => s.push('\n'),
+                        't' => s.push('\t'),
+                        'r' => s.push('\r'),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.i).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("bad \\u escape"))?;
+                            s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                            self.i += 4;
+                        }
+                        _ => s.push(e),
+                    }
+                }
+                _ => s.push(c),
+            }
+        }
+    }
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.i += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.i += 1,
+                Some(']') => { self.i += 1; return Ok(Json::Array(items)); }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.i += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.i += 1,
+                Some('}') => { self.i += 1; return Ok(Json::Object(members)); }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        let json = Json::parse(r#" { "a": [1, -2.5e1, true, false, null], "b": { "c": "x\"é\n" } } "#).unwrap();
+        let a = json.get("a").and_then(Json::as_array).unwrap();
+        assert_eq!(a, &[Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Bool(false), Json::Null]);
+        assert_eq!(json.get("b").and_then(|b| b.get("c")).and_then(Json::as_str), Some("x\"é\n"));
+        assert_eq!(json.as_object().unwrap().iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(Json::parse("[]").unwrap(), Json::Array(Vec::new()));
+        assert_eq!(Json::parse("// comment\n{}").unwrap(), Json::Object(Vec::new()));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Json::Number(3.0).as_usize(), Some(3));
+        assert_eq!(Json::Number(3.5).as_usize(), None);
+        assert_eq!(Json::Number(-1.0).as_usize(), None);
+        assert_eq!(Json::parse("[1, 2]").unwrap().as_floats(), Some(vec![1.0, 2.0]));
+        assert_eq!(Json::parse("[1, \"2\"]").unwrap().as_floats(), None);
+        assert_eq!(Json::Null.get("a"), None);
+    }
+
+    #[test]
+    fn errors_have_the_position() {
+        assert_eq!(Json::parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err(), "3:7: expected ':'");
+        assert_eq!(Json::parse("[1, 2").unwrap_err(), "1:6: expected ',' or ']'");
+        assert_eq!(Json::parse("\"abc").unwrap_err(), "1:5: unterminated string");
+        assert_eq!(Json::parse("[1] 2").unwrap_err(), "1:5: unexpected text after the value");
+        assert_eq!(Json::parse("").unwrap_err(), "1:1: unexpected end of file");
+        assert!(Json::parse("[1.2.3]").unwrap_err().contains("bad number '1.2.3'"));
+        assert!(Json::parse("tru").unwrap_err().contains("expected a value"));
+        assert!(Json::parse("{\"a\": 1,}").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use glam::{Vec3, Mat4};
+
+use crate::canvas::{HEIGHT, WIDTH};
+use crate::color::Color;
+use crate::mesh::Mesh;
+use crate::shadow::ShadowMap;
+
+pub const AMBIENT: Color = Color::splat(0.1);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShadingMode {
+    Gouraud,    // Light per vertex, interpolated across the triangle
+    Phong,      // Normal and position interpolated, light per pixel
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LightKind {
+    Point,
+    Directional(Vec3),                  // Direction the light travels in
+    Spot { dir: Vec3, cutoff: f32 },    // Cutoff is the half angle of the cone in degrees
+}
+
+pub struct Light {
+    pub kind: LightKind,
+    pub pos: Vec3,      // For directional lights, center of the shadowed area
+    pub color: Color,
+    pub range: Option<f32>,     // Point and spot lights fade out to nothing at this distance
+    pub shadow: Option<ShadowMap>,
+}
+impl Light {
+    pub fn new(pos: Vec3, color: Color) -> Self {
+        Self { kind: LightKind::Point, pos, color, range: None, shadow: None }
+    }
+    pub fn directional(dir: Vec3, color: Color) -> Self {
+        Self { kind: LightKind::Directional(dir.normalize()), pos: Vec3::ZERO, color, range: None, shadow: None }
+    }
+    pub fn spot(pos: Vec3, dir: Vec3, cutoff: f32, color: Color) -> Self {
+        Self { kind: LightKind::Spot { dir: dir.normalize(), cutoff }, pos, color, range: None, shadow: None }
+    }
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = Some(range);
+        return self;
+    }
+    pub fn with_shadow(mut self, shadow: ShadowMap) -> Self {
+        self.shadow = Some(shadow);
+        return self;
+    }
+    // Diffuse strength for a world space point and its normal
+    pub fn diffuse(&self, normal: Vec3, pos: Vec3) -> f32 {
+        let to_light = match self.kind {
+            LightKind::Point => (self.pos - pos).normalize(),
+            LightKind::Directional(dir) => -dir,
+            LightKind::Spot { dir, cutoff } => {
+                let to_light = (self.pos - pos).normalize();
+                // Soften the last 10% of the cone
+                let cos_outer = cutoff.to_radians().cos();
+                let cos_inner = (cutoff * 0.9).to_radians().cos();
+                let spot = ((Vec3::dot(-to_light, dir) - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+                if spot == 0.0 {
+                    return 0.0;
+                }
+                return Vec3::dot(normal, to_light).clamp(0.0, 1.0) * spot;
+            }
+        };
+        return Vec3::dot(normal, to_light).clamp(0.0, 1.0);
+    }
+    // Light color reaching the point
+    pub fn radiance(&self, normal: Vec3, pos: Vec3) -> Color {
+        return self.color.scale_rgb(self.diffuse(normal, pos) * self.attenuation(pos));
+    }
+    // Radiance with the shadow map applied
+    pub fn radiance_shadowed(&self, normal: Vec3, pos: Vec3) -> Color {
+        match &self.shadow {
+            Some(shadow) => self.radiance(normal, pos).scale_rgb(shadow.visibility(pos)),
+            None => self.radiance(normal, pos),
+        }
+    }
+    // Windowed falloff, reaches exactly 0 at range
+    pub fn attenuation(&self, pos: Vec3) -> f32 {
+        match (self.kind, self.range) {
+            (LightKind::Directional(_), _) | (_, None) => 1.0,
+            (_, Some(range)) => {
+                let d = (self.pos - pos).length() / range;
+                (1.0 - d * d).max(0.0).powi(2)
+            }
+        }
+    }
+    // View and projection matrices used to render the shadow map, point lights aren't supported
+    pub fn shadow_matrices(&self, extent: f32, far: f32) -> Option<(Mat4, Mat4)> {
+        let aspect = WIDTH as f32 / HEIGHT as f32;
+        match self.kind {
+            LightKind::Point => None,
+            LightKind::Directional(dir) => {
+                let eye = self.pos - dir * far * 0.5;
+                let view = Mat4::look_at_rh(eye, self.pos, up_for(dir));
+                let proj = Mat4::orthographic_rh(-extent * aspect, extent * aspect, -extent, extent, 0.0, far);
+                Some((view, proj))
+            }
+            LightKind::Spot { dir, cutoff } => {
+                let view = Mat4::look_at_rh(self.pos, self.pos + dir, up_for(dir));
+                let proj = Mat4::perspective_rh((cutoff * 2.0).to_radians(), aspect, 0.1, far);
+                Some((view, proj))
+            }
+        }
+    }
+    // Renders the shadow casters into the shadow map, does nothing if the light has none
+    pub fn update_shadow(&mut self, casters: &[(&Mesh, Mat4)]) {
+        let Some(shadow) = &self.shadow else { return };
+        let Some((view, proj)) = self.shadow_matrices(shadow.extent, shadow.far) else { return };
+        self.shadow.as_mut().unwrap().render(view, proj, casters);
+    }
+}
+
+// Any up vector that isn't parallel to the direction
+fn up_for(dir: Vec3) -> Vec3 {
+    if dir.cross(Vec3::Y).length_squared() < 0.001 {
+        return Vec3::Z;
+    }
+    return Vec3::Y;
+}
@@ -1,16 +1,30 @@
 use std::time::{Duration, Instant};
-use glam::{Vec3, Quat, IVec2, Vec2};
-use mesh::Vertex;
-use model::Model;
+use glam::{Vec3, Quat, IVec2, Vec2, Mat4};
+use mesh::{Mesh, Vertex};
+use model::{Model, Lod};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 
 use canvas::{Canvas, HEIGHT, WIDTH, W_WIDTH, W_HEIGHT};
-use renderer::Renderer;
+use renderer::{Renderer, SortMode, CullStats, Pick, Surface};
 use shapes_textured::*;
-use utils::{default_mat_proj};
 use camera::*;
+use lighting::{Light, LightKind, ShadingMode};
+use material::Material;
+use shadow::ShadowMap;
+use gbuffer::GBuffer;
+use shader::{NormalShader, NormalUniforms, DefaultVertexShader, DefaultFragmentShader, Pair};
+use color::{Color, BlendMode};
+use fog::FogMode;
+use cubemap::CubeMap;
+use simplify::{simplify, SimplifyTarget};
+use animation::{Animation, Clip, Track, Keyframe, Interpolation, LoopMode};
+use std::rc::Rc;
+use text::{draw_text, GLYPH_WIDTH};
+use scene::{Scene, Node};
+use scene_file::load_scene;
+use particles::{Emitter, soft_dot};
 
 mod shapes;
 mod utils;
@@ -21,15 +35,55 @@
 mod clipping;
 mod camera;
 mod shapes_textured;
+mod lighting;
+mod color;
+mod material;
+mod fog;
+mod shadow;
+mod shader;
+mod gbuffer;
+mod cubemap;
+mod render_texture;
+mod assets;
+mod text;
+mod scene;
+mod scene_file;
+mod json;
+mod cli;
+mod bounds;
+mod bvh;
+mod simplify;
+mod animation;
+mod skeleton;
+mod gltf;
+mod md2;
+mod particles;
 
 // TODO:
-// raster data vector, animations, specular light, color struct, fog, light color
+// raster data vector, animations, specular light
 // DONE:
 // Normal face culling, Depth sorting, Near and Viewport clipping, lighting, color interpolation,
 // smooth shading, camera, fix screen clipping lighting, textures, fix texture bug
 // flip horizontal and rotate 180 texture (wrote bash), texture lit, clipping lit update, zbuffer, 
+// per pixel (phong) lighting, color struct, light color, fog, shadow mapping,
+// alpha blending, vertex and fragment shaders, deferred shading, normal mapping,
+// skybox, reflections, render to texture, asset manager, hot reloading,
+// scene graph, scene files, instancing, frustum culling,
+// ray picking, level of detail, mesh simplification, keyframe animation,
+// skeletal animation, glTF, morph targets, MD2 and OBJ sequence vertex animation,
+// particles, orthographic projection
 
 fn main() {
+    // Headless subcommands, drawing render <scene.json> [output.ppm]
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(result) = cli::run(&args) {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // SDL Init
     let sdl_context = sdl2::init().unwrap();
     sdl_context.mouse().show_cursor(false);
@@ -59,28 +113,256 @@ fn main() {
     // End SDL Init
 
     let mut canvas = Canvas::new();
-    let mut renderer = Renderer::new(default_mat_proj());
+    let mut renderer = Renderer::new();
     let mut camera = Camera::new(Vec3::new(0.0, 0.0, -2.5), 0.10, 0.15);
+    let mut background = Color::from_u32(0xFF020202);
+    renderer.fog.color = background;
+    // Optional skybox, six faces or an equirectangular panorama converted with tex.sh
+    if std::path::Path::new("textures/sky_px.raw").exists() {
+        renderer.environment = Some(CubeMap::from_faces([
+            "textures/sky_px.raw", "textures/sky_nx.raw",
+            "textures/sky_py.raw", "textures/sky_ny.raw",
+            "textures/sky_pz.raw", "textures/sky_nz.raw",
+        ]));
+    } else if std::path::Path::new("textures/sky.raw").exists() {
+        renderer.environment = Some(CubeMap::from_equirectangular("textures/sky.raw", 512));
+    }
 
-    let obj_tex = renderer.load_texture("textures/arctic.raw");
-    let mut obj = Model::new("models/arctic_run.obj", obj_tex);
-    let mut obj2 = Model::new("models/arctic.obj", obj_tex);
+    let obj_tex = renderer.assets.load_texture("textures/arctic.raw");
+    let mut obj = Model::new(renderer.assets.load_mesh("models/arctic_run.obj"), Some(obj_tex));
+    let mut obj2 = Model::new(renderer.assets.load_mesh("models/arctic.obj"), Some(obj_tex));
     obj.translation.x = 0.85;
     obj2.translation.x = -0.85;
     obj2.translation.y = 0.2;
 
+    // Turntable clips, a full turn around y in the given seconds, negative turns the other way
+    let turn = |name: &str, seconds: f32| {
+        let keys = (0..=4).map(|i| Keyframe::new(i as f32 * seconds.abs() / 4.0, Quat::from_rotation_y(seconds.signum() * i as f32 * std::f32::consts::FRAC_PI_2)));
+        Rc::new(Clip::new(name).with_tracks(Track::empty(), Track::new(keys.collect(), Interpolation::Linear), Track::empty()))
+    };
+    obj.animation = Some(Animation::new(turn("spin", 5.4), LoopMode::Loop));
+    obj2.animation = Some(Animation::new(turn("spin_back", -5.4), LoopMode::Loop));
+
+    // Same models as a hierarchy, the small copy and the lamp follow the right model
+    let mut scene = Scene::new();
+    let root = scene.add(None, Node::new("root"));
+    let right = scene.add(Some(root), Node::new("arctic_run").with_mesh(obj.mesh, Some(obj_tex)).with_translation(Vec3::new(0.85, 0.0, 0.0)));
+    scene.add(Some(root), Node::new("arctic").with_mesh(obj2.mesh, Some(obj_tex)).with_translation(Vec3::new(-0.85, 0.2, 0.0)));
+    let small = scene.add(Some(right), Node::new("small").with_mesh(obj2.mesh, Some(obj_tex)).with_translation(Vec3::new(0.0, 0.9, 0.0)));
+    scene.node_mut(small).scale = Vec3::splat(0.3);
+    scene.node_mut(root).animation = Some(Animation::new(turn("root_spin", 12.0), LoopMode::Loop));
+    let bob = [0.9, 1.1, 1.0].iter().enumerate().map(|(i, y)| Keyframe::new(i as f32 * 0.75, Vec3::new(0.0, *y, 0.0)));
+    let bob = Clip::new("bob").with_tracks(Track::new(bob.collect(), Interpolation::Cubic), Track::empty(), Track::empty());
+    scene.node_mut(small).animation = Some(Animation::new(Rc::new(bob), LoopMode::PingPong));
+    scene.add(Some(right), Node::new("lamp").with_light(Light::new(Vec3::new(0.0, 0.5, -0.6), Color::rgb(1.0, 0.6, 0.2)).with_range(2.0)));
+    scene.active_camera = Some(scene.add(Some(root), Node::new("eye").with_camera(camera.clone()).with_translation(Vec3::new(0.0, 0.5, -3.0))));
+    let mut scene_view = 0;     // 0 = off, 1 = free camera, 2 = scene camera
+    let mut demo_scene = true;
+
+    // Scene file given on the command line replaces the demo scene
+    if let Some(path) = args.get(1) {
+        let file = load_scene(path, &mut renderer).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        scene = file.scene;
+        camera = file.camera;
+        background = file.background;
+        demo_scene = false;
+        scene_view = if scene.active_camera.is_some() { 2 } else { 1 };
+    }
+
+    // Grid of small cubes sharing one mesh, drawn with per instance tints
+    let cube = renderer.assets.load_mesh("models/cube.obj");
+    let crate_tex = renderer.assets.load_texture("textures/crate.raw");
+    let mut cubes = Vec::<Mat4>::new();
+    let mut cube_tints = Vec::<Color>::new();
+    for z in 0..6 {
+        for x in 0..10 {
+            let pos = Vec3::new(x as f32 * 0.6 - 2.7, -1.2, z as f32 * 0.6 + 1.0);
+            cubes.push(Mat4::from_scale_rotation_translation(Vec3::splat(0.15), Quat::IDENTITY, pos));
+            cube_tints.push(Color::rgb(0.5 + x as f32 / 20.0, 0.5 + z as f32 / 12.0, 1.0 - x as f32 / 20.0));
+        }
+    }
+    let mut show_cubes = false;
+
+    // Skinned tube from a glTF file, bends with the animation that came with it
+    let mut tube = Model::new(renderer.assets.load_mesh("models/tube.gltf"), Some(crate_tex));
+    tube.translation.y = -0.6;
+    if let Some(clip) = renderer.assets.mesh(tube.mesh).and_then(|m| m.clip("bend")) {
+        tube.skeleton_animation = Some(Animation::new(clip, LoopMode::Loop));
+    }
+    let mut show_tube = false;
+
+    // Sparks and smoke between the models, P turns them on
+    let dot = renderer.assets.add_texture("particle", soft_dot(32));
+    let mut sparks = Emitter::new(Vec3::new(0.0, -0.6, 0.0), 150.0);
+    sparks.lifetime = (0.6, 1.2);
+    sparks.velocity = Vec3::new(0.0, 1.5, 0.0);
+    sparks.spread = 0.6;
+    sparks.gravity = Vec3::new(0.0, -3.0, 0.0);
+    sparks.color = (Color::new(1.0, 0.8, 0.3, 1.0), Color::new(1.0, 0.2, 0.0, 0.0));
+    sparks.size = (0.06, 0.02);
+    sparks.texture = Some(dot);
+    sparks.blend = BlendMode::Additive;
+    let mut smoke = Emitter::new(Vec3::new(0.0, -0.6, 0.0), 15.0);
+    smoke.area = Vec3::new(0.1, 0.0, 0.1);
+    smoke.lifetime = (2.0, 3.0);
+    smoke.velocity = Vec3::new(0.0, 0.4, 0.0);
+    smoke.spread = 0.1;
+    smoke.color = (Color::new(0.6, 0.6, 0.6, 0.6), Color::new(0.3, 0.3, 0.3, 0.0));
+    smoke.size = (0.15, 0.6);
+    smoke.texture = Some(dot);
+    let mut show_particles = false;
+
+    // Running pose as a morph target of the left model, M blends between the two
+    let mut arctic = Mesh::load("models/arctic.obj").unwrap_or_else(|e| panic!("{}", e));
+    let run = Mesh::load("models/arctic_run.obj").unwrap_or_else(|e| panic!("{}", e));
+    arctic.add_morph_target("run", &run).unwrap_or_else(|e| panic!("{}", e));
+    renderer.assets.set_mesh(obj2.mesh, arctic);
+    let mut morphing = false;
+
+    // The two OBJs as frames of a vertex animation on the right model, V plays it
+    let frames = Mesh::from_obj_frames("run", &["models/arctic_run.obj", "models/arctic.obj"]).unwrap_or_else(|e| panic!("{}", e));
+    let run_clip = frames.frame_clip("run");
+    renderer.assets.set_mesh(obj.mesh, frames);
+
+    // Lower detail levels of the left model, walk away from it to see them switch
+    if let Some(mesh) = renderer.assets.mesh(obj2.mesh) {
+        let half = simplify(mesh, SimplifyTarget { triangles: mesh.triangles.len() / 2, max_error: f32::INFINITY });
+        let quarter = simplify(mesh, SimplifyTarget { triangles: mesh.triangles.len() / 4, max_error: f32::INFINITY });
+        obj2.lods.push(Lod { mesh: renderer.assets.add_mesh("arctic.lod1", half), screen_size: 0.3 });
+        obj2.lods.push(Lod { mesh: renderer.assets.add_mesh("arctic.lod2", quarter), screen_size: 0.15 });
+    }
+
+    let mut gbuffer = GBuffer::new();
+    let mut show_normals = false;
+    // Second camera rendered into a texture, color and normals in one pass
+    let mut monitor = renderer.create_render_texture("monitor", 1, 4);
+    let mut monitor_camera = Camera::new(Vec3::new(0.0, 0.0, -4.0), 0.10, 0.15);
+    monitor_camera.look(-40.0, 0.0);
+    let mut monitor_view = 0;   // 0 = off, 1 = color, 2 = normals
+    let mut deferred = false;
+
+    let mut reload_errors = Vec::<String>::new();
+    let mut picked: Option<Pick> = None;
+
     let mut frame = 0;
     let mut last_mouse_x = 0.0;
+    let mut last_frame = Instant::now();
     let mut last_mouse_y = 0.0;
 
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::MouseButtonDown { x, y, .. } => {
+                    // Window to canvas pixels
+                    let x = x as f32 * WIDTH as f32 / W_WIDTH as f32;
+                    let y = y as f32 * HEIGHT as f32 / W_HEIGHT as f32;
+                    picked = renderer.pick(&[&obj, &obj2], &camera, x, y);
+                    if let Some(p) = picked {
+                        println!("Picked model {} triangle {} at {:?}, barycentrics {:?}, uv {:?}", p.model, p.hit.triangle, p.world, p.hit.bary, p.hit.uv);
+                    }
+                }
                 Event::KeyDown { keycode: Some(keycode), .. } => {
                     match keycode {
                         Keycode::Escape => break 'running,
                         Keycode::F1 => { renderer.wireframe = !renderer.wireframe }
+                        Keycode::F2 => {
+                            renderer.shading = match renderer.shading {
+                                ShadingMode::Gouraud => ShadingMode::Phong,
+                                ShadingMode::Phong => ShadingMode::Gouraud,
+                            }
+                        }
+                        Keycode::F3 => {
+                            renderer.fog.mode = match renderer.fog.mode {
+                                FogMode::Off => FogMode::Linear,
+                                FogMode::Linear => FogMode::Exp,
+                                FogMode::Exp => FogMode::Exp2,
+                                FogMode::Exp2 => FogMode::Off,
+                            }
+                        }
+                        Keycode::F5 => {
+                            // Cycle blend modes on the left model
+                            let old = obj2.material.diffuse;
+                            (obj2.material.blend, obj2.material.diffuse.a) = match obj2.material.blend {
+                                BlendMode::Opaque => (BlendMode::Alpha, 0.5),
+                                BlendMode::Alpha => (BlendMode::Additive, 0.5),
+                                BlendMode::Additive => (BlendMode::Multiply, 1.0),
+                                BlendMode::Multiply => (BlendMode::Premultiplied, 0.5),
+                                BlendMode::Premultiplied => (BlendMode::Opaque, 1.0),
+                            };
+                            // Premultiplied colors carry the alpha in rgb too, undo it on the way out
+                            let diffuse = &mut obj2.material.diffuse;
+                            match obj2.material.blend {
+                                BlendMode::Premultiplied => *diffuse = diffuse.scale_rgb(diffuse.a),
+                                BlendMode::Opaque => *diffuse = diffuse.scale_rgb(1.0 / old.a),
+                                _ => (),
+                            }
+                        }
+                        Keycode::F6 => {
+                            renderer.sort_mode = match renderer.sort_mode {
+                                SortMode::PerModel => SortMode::PerTriangle,
+                                SortMode::PerTriangle => SortMode::PerModel,
+                            }
+                        }
+                        Keycode::F7 => { show_normals = !show_normals }
+                        Keycode::F8 => { deferred = !deferred }
+                        Keycode::F11 => {
+                            // Show the monitor camera on the left model
+                            monitor_view = (monitor_view + 1) % 3;
+                            obj2.texture = Some(if monitor_view == 0 { obj_tex } else { monitor.textures[monitor_view - 1] });
+                        }
+                        Keycode::F12 => { scene_view = (scene_view + 1) % 3 }
+                        Keycode::I => { show_cubes = !show_cubes }
+                        Keycode::K => { show_tube = !show_tube }
+                        Keycode::M => {
+                            morphing = !morphing;
+                            obj2.morph_weights.clear();
+                        }
+                        Keycode::O => {
+                            camera.projection = match camera.projection {
+                                Projection::Perspective { .. } => Projection::orthographic(3.0 * WIDTH as f32 / HEIGHT as f32, 3.0, 0.1, 1000.0),
+                                Projection::Orthographic { .. } => Projection::default(),
+                            }
+                        }
+                        Keycode::Equals | Keycode::Minus => {
+                            if let Projection::Perspective { fov, .. } = &mut camera.projection {
+                                *fov = (*fov + if keycode == Keycode::Equals { -5.0 } else { 5.0 }).clamp(10.0, 150.0);
+                            }
+                        }
+                        Keycode::P => { show_particles = !show_particles }
+                        Keycode::V => {
+                            obj.vertex_animation = match obj.vertex_animation {
+                                Some(_) => None,
+                                None => run_clip.clone().map(|clip| Animation { speed: 0.2, ..Animation::new(clip, LoopMode::Loop) }),
+                            };
+                            obj.morph_weights.clear();
+                        }
+                        Keycode::L => { renderer.lod_fade_time = if renderer.lod_fade_time > 0.0 { 0.0 } else { 0.5 } }
+                        Keycode::F10 => {
+                            // Toggle environment reflections on the right model
+                            obj.material.reflectivity = if obj.material.reflectivity > 0.0 { 0.0 } else { 0.6 };
+                        }
+                        Keycode::F9 => {
+                            // Toggle small colored lights circling the models
+                            if renderer.lights.len() > 1 {
+                                renderer.lights.truncate(1);
+                            } else {
+                                for color in [Color::rgb(1.0, 0.2, 0.2), Color::rgb(0.2, 1.0, 0.2), Color::rgb(0.2, 0.2, 1.0)] {
+                                    renderer.lights.push(Light::new(Vec3::ZERO, color).with_range(1.5));
+                                }
+                            }
+                        }
+                        Keycode::F4 => {
+                            // Cycle the default point light and shadow casting sun / spot lights
+                            renderer.lights[0] = match renderer.lights[0].kind {
+                                LightKind::Point => Light::directional(Vec3::new(1.0, -0.3, 0.5), Color::WHITE).with_shadow(ShadowMap::new()),
+                                LightKind::Directional(_) => Light::spot(Vec3::new(-3.0, 1.0, -1.5), Vec3::new(1.0, -0.3, 0.5), 35.0, Color::WHITE).with_shadow(ShadowMap::new()),
+                                LightKind::Spot { .. } => Light::new(Vec3::new(0.0, 0.0, -1.0), Color::WHITE),
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -115,17 +397,114 @@ fn main() {
         //camera.look(change_x, change_y);
         // END Process input
 
+        let dt = last_frame.elapsed().as_secs_f32();
+        last_frame = Instant::now();
         let start = Instant::now();
         frame += 1;
-        canvas.clear(0xFF020202);
+        canvas.clear(background);
+        renderer.cull_stats = CullStats::default();
 
         // -----------GAME LOOP------------ //
-        obj.rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), (frame as f32 / 0.9).to_radians());
-        obj2.rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), (-frame as f32 / 0.9).to_radians());
-        renderer.draw(&obj, &camera, &mut canvas);
-        renderer.draw(&obj2, &camera, &mut canvas);
+        obj.animate(dt);
+        obj2.animate(dt);
+        tube.animate(dt);
+        if show_particles {
+            sparks.update(dt);
+            smoke.update(dt);
+        }
+        if morphing {
+            obj2.morph_weights = vec![(frame as f32 * 3.0).to_radians().sin() * 0.5 + 0.5];
+        }
+        renderer.update_lod(&mut obj, &camera, dt);
+        renderer.update_lod(&mut obj2, &camera, dt);
+        let light_count = renderer.lights.len();
+        for (i, light) in renderer.lights.iter_mut().skip(1).enumerate() {
+            let angle = (frame as f32 * 2.0).to_radians() + i as f32 * std::f32::consts::TAU / (light_count - 1) as f32;
+            light.pos = Vec3::new(angle.cos() * 1.5, 0.3, angle.sin() * 1.5);
+        }
+        if monitor_view != 0 {
+            monitor.clear(background);
+            for o in [&obj, &obj2] {
+                let Some(mesh) = renderer.assets.mesh(o.mesh) else { continue };
+                let uniforms = renderer.uniforms(o, &monitor_camera);
+                let shader = Pair(DefaultFragmentShader, NormalShader);
+                renderer.draw_with(mesh, (&DefaultVertexShader, &shader), &uniforms, &monitor_camera.projection, BlendMode::Opaque, &mut monitor.target());
+            }
+            renderer.resolve(&monitor);
+        }
+        if scene_view != 0 {
+            scene.animate(dt);
+            if demo_scene {
+                scene.node_mut(right).rotation = obj.rotation;
+            }
+            let scene_camera = if scene_view == 2 { scene.camera().unwrap_or(camera.clone()) } else { camera.clone() };
+            renderer.render_scene(&mut scene, &scene_camera, &mut canvas);
+        } else if show_normals {
+            for o in [&obj, &obj2] {
+                let Some(mesh) = renderer.assets.mesh(o.mesh) else { continue };
+                let uniforms = NormalUniforms { mat_model: o.get_model_mat(), mat_view: camera.get_view_mat() };
+                renderer.draw_with(mesh, (&NormalShader, &NormalShader), &uniforms, &camera.projection, BlendMode::Opaque, &mut canvas);
+            }
+        } else if deferred {
+            renderer.render_shadows(&[&obj, &obj2]);
+            gbuffer.clear();
+            renderer.draw_deferred(&obj, &camera, &mut gbuffer, &mut canvas);
+            renderer.draw_deferred(&obj2, &camera, &mut gbuffer, &mut canvas);
+            renderer.light_gbuffer(&mut gbuffer, &camera, &mut canvas);
+            if show_particles {
+                renderer.draw_particles(&smoke, &camera, &mut canvas);
+                renderer.draw_particles(&sparks, &camera, &mut canvas);
+            }
+            renderer.draw_skybox(&camera, &mut canvas);
+            renderer.draw_transparent(&camera, &mut canvas);
+        } else {
+            let casters = [&obj, &obj2, &tube];
+            renderer.render_shadows(&casters[..if show_tube { 3 } else { 2 }]);
+            renderer.draw(&obj, &camera, &mut canvas);
+            renderer.draw(&obj2, &camera, &mut canvas);
+            if show_tube {
+                renderer.draw(&tube, &camera, &mut canvas);
+            }
+            if show_cubes {
+                renderer.draw_instanced(Surface { mesh: cube, texture: Some(crate_tex), material: &Material::default() }, &cubes, &cube_tints, &camera, &mut canvas);
+            }
+            if show_particles {
+                renderer.draw_particles(&smoke, &camera, &mut canvas);
+                renderer.draw_particles(&sparks, &camera, &mut canvas);
+            }
+            renderer.draw_skybox(&camera, &mut canvas);
+            renderer.draw_transparent(&camera, &mut canvas);
+        }
         // -------------------------------- //
 
+        // Outline the picked triangle, it follows the model around
+        if let Some(p) = picked {
+            let model = [&obj, &obj2][p.model];
+            if let Some(tri) = renderer.assets.mesh(model.mesh).and_then(|m| m.triangles.get(p.hit.triangle)) {
+                let points = tri.v.map(|v| renderer.world_to_canvas(&camera, model.get_model_mat().transform_point3(v.pos)));
+                let on_canvas = |p: &Option<Vec3>| p.is_some_and(|p| p.x >= 0.0 && p.y >= 0.0 && p.x < WIDTH as f32 - 1.0 && p.y < HEIGHT as f32 - 1.0);
+                if points.iter().all(on_canvas) {
+                    let [a, b, c] = points.map(|p| p.unwrap().truncate().as_ivec2());
+                    let highlight = Color::rgb(1.0, 1.0, 0.0);
+                    shapes::draw_triangle(&mut canvas, a, b, c, highlight, highlight, highlight, false);
+                }
+            }
+            let info = format!("model {} triangle {} bary {:.2} {:.2} {:.2} uv {:.2} {:.2}",
+                p.model, p.hit.triangle, p.hit.bary.x, p.hit.bary.y, p.hit.bary.z, p.hit.uv.x, p.hit.uv.y);
+            draw_text(&mut canvas, 10, HEIGHT as i32 - 25, 3, &info, Color::rgb(1.0, 1.0, 0.0));
+        }
+
+        // Hot reload changed models and textures, broken files keep the old asset
+        if frame % 30 == 0 && renderer.assets.reload_changed() {
+            reload_errors = renderer.assets.errors();
+        }
+        for (i, error) in reload_errors.iter().enumerate() {
+            draw_text(&mut canvas, 10, 10 + i as i32 * 21, 3, error, Color::rgb(1.0, 0.3, 0.3));
+        }
+        // Frustum culling, top right
+        let stats = format!("culled {}/{}", renderer.cull_stats.culled, renderer.cull_stats.tested);
+        draw_text(&mut canvas, WIDTH as i32 - 10 - stats.len() as i32 * (GLYPH_WIDTH + 1) * 3, 10, 3, &stats, Color::rgb(0.6, 1.0, 0.6));
+
         let duration = start.elapsed();
         println!("Frametime: {:?}", duration);
 
@@ -0,0 +1,23 @@
+use crate::color::{Color, BlendMode};
+use crate::assets::TextureHandle;
+
+#[derive(Clone)]
+pub struct Material {
+    pub diffuse: Color,     // Tints the vertex color / texture
+    pub normal_map: Option<TextureHandle>,
+    pub blend: BlendMode,   // Anything but Opaque is drawn in the transparent pass
+    pub alpha_cutoff: Option<f32>,  // Pixels with less alpha are discarded (foliage, fences)
+    pub reflectivity: f32,  // Mixes in the renderer environment, 0 = none, 1 = mirror
+    pub unlit: bool,        // Color and texture only, no lights or reflections (particles), forward path only
+    pub id: u16,            // Written to the G-buffer by the deferred path, 0 is reserved for empty
+}
+impl Material {
+    pub fn new(diffuse: Color) -> Self {
+        Self { diffuse, normal_map: None, blend: BlendMode::Opaque, alpha_cutoff: None, reflectivity: 0.0, unlit: false, id: 1 }
+    }
+}
+impl Default for Material {
+    fn default() -> Self {
+        Self::new(Color::WHITE)
+    }
+}
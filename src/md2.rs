@@ -0,0 +1,168 @@
+use glam::{Vec2, Vec3};
+use std::fs;
+use std::rc::Rc;
+
+use crate::animation::FrameClip;
+use crate::color::Color;
+use crate::mesh::{Mesh, MorphDelta, MorphTarget, Triangle, Vertex};
+
+// Quake 2 model. Frame 0 is the mesh and every frame is also a morph target named like in the
+// file ("run3"), frames with the same name up to the number become a clip ("run") playing at
+// 10 frames per second like in the game. Z up is turned into Y up, the size is left in Quake
+// units (scale the model down). Skins are ignored, textures are loaded like any other.
+pub fn load_md2(path: &str) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    return parse(&bytes).map_err(|e| format!("{}: {}", path, e));
+}
+
+fn parse(bytes: &[u8]) -> Result<Mesh, String> {
+    let read = |offset: usize, len: usize, what: &str| bytes.get(offset..offset + len).ok_or(format!("truncated {}", what));
+    let int = |i: usize| -> Result<usize, String> {
+        let v = i32::from_le_bytes(read(i * 4, 4, "header")?.try_into().unwrap());
+        return usize::try_from(v).map_err(|_| "negative value in the header".to_string());
+    };
+    if !bytes.starts_with(b"IDP2") || int(1)? != 8 {
+        return Err("not an MD2 version 8 file".to_string());
+    }
+    let header = (2..17).map(int).collect::<Result<Vec<_>, _>>()?;
+    let [skin_width, skin_height, frame_size, _, vertex_count, st_count, tri_count, _, frame_count, _, ofs_st, ofs_tris, ofs_frames, ..] = header[..] else {
+        return Err("truncated header".to_string());
+    };
+    if frame_count == 0 || tri_count == 0 {
+        return Err("no frames or no triangles".to_string());
+    }
+
+    let mut uvs = Vec::with_capacity(st_count);
+    for i in 0..st_count {
+        let st = read(ofs_st + i * 4, 4, "texture coordinates")?;
+        let (s, t) = (i16::from_le_bytes([st[0], st[1]]), i16::from_le_bytes([st[2], st[3]]));
+        uvs.push(Vec2::new(s as f32 / skin_width.max(1) as f32, 1.0 - t as f32 / skin_height.max(1) as f32));
+    }
+    let mut tris = Vec::with_capacity(tri_count);
+    for i in 0..tri_count {
+        let t = read(ofs_tris + i * 12, 12, "triangles")?;
+        let index: Vec<usize> = t.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).collect();
+        // Quake 2 front faces are clockwise
+        let (vertices, sts) = ([index[0], index[2], index[1]], [index[3], index[5], index[4]]);
+        if vertices.iter().any(|v| *v >= vertex_count) || sts.iter().any(|s| *s >= st_count) {
+            return Err(format!("triangle {}: index out of range", i));
+        }
+        tris.push((vertices, sts));
+    }
+
+    // Positions are bytes scaled and moved per frame
+    let mut frames = Vec::with_capacity(frame_count);
+    for f in 0..frame_count {
+        let frame = read(ofs_frames + f * frame_size, 40 + vertex_count * 4, "frames")?;
+        let float = |i: usize| f32::from_le_bytes(frame[i * 4..i * 4 + 4].try_into().unwrap());
+        let (scale, translate) = (Vec3::new(float(0), float(1), float(2)), Vec3::new(float(3), float(4), float(5)));
+        let name = String::from_utf8_lossy(&frame[24..40]).split('\0').next().unwrap_or("").to_string();
+        let pos: Vec<Vec3> = frame[40..].chunks_exact(4).map(|v| {
+            let p = Vec3::new(v[0] as f32, v[1] as f32, v[2] as f32) * scale + translate;
+            Vec3::new(p.x, p.z, -p.y)
+        }).collect();
+        // The normal table isn't worth carrying around, smooth normals from the shape instead
+        let mut normals = vec![Vec3::ZERO; vertex_count];
+        for (v, _) in tris.iter() {
+            let n = (pos[v[1]] - pos[v[0]]).cross(pos[v[2]] - pos[v[0]]);
+            v.iter().for_each(|i| normals[*i] += n);
+        }
+        normals.iter_mut().for_each(|n| *n = n.normalize_or_zero());
+        frames.push((name, pos, normals));
+    }
+
+    let mut mesh = Mesh::new();
+    let (_, base, base_normals) = &frames[0];
+    for (v, st) in tris.iter() {
+        let vertex = |i: usize| Vertex::new(base[v[i]], base_normals[v[i]], uvs[st[i]], Color::WHITE);
+        mesh.triangles.push(Triangle::new(vertex(0), vertex(1), vertex(2)));
+    }
+    for (name, pos, normals) in frames.iter() {
+        let mut target = MorphTarget::new(name, 0.0, tri_count);
+        for (deltas, (v, _)) in target.deltas.iter_mut().zip(tris.iter()) {
+            for (d, i) in deltas.v.iter_mut().zip(v.iter()) {
+                *d = MorphDelta { pos: pos[*i] - base[*i], normal: normals[*i] - base_normals[*i] };
+            }
+        }
+        mesh.morph_targets.push(target);
+    }
+
+    let mut start = 0;
+    for f in 1..=frames.len() {
+        let clip = |f: usize| frames[f].0.trim_end_matches(|c: char| c.is_ascii_digit());
+        if f == frames.len() || clip(f) != clip(start) {
+            mesh.frame_clips.push(Rc::new(FrameClip::new(clip(start), (start..f).collect(), 10.0)));
+            start = f;
+        }
+    }
+
+    mesh.generate_tangents();
+    mesh.compute_bounds();
+    mesh.build_bvh();
+    return Ok(mesh);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One triangle, frames "stand1" (in the XY plane of the file) and "run1" "run2" moved up by 1 and 2
+    fn model() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let frame_size = 40 + 3 * 4;
+        let header = [8, 64, 32, frame_size, 0, 3, 3, 1, 0, 3, 68, 68, 80, 92, 92, 92 + 3 * frame_size];
+        bytes.extend(b"IDP2");
+        header.iter().for_each(|v: &i32| bytes.extend(v.to_le_bytes()));
+        [[0i16, 0], [64, 0], [0, 32]].iter().flatten().for_each(|v| bytes.extend(v.to_le_bytes()));
+        [0u16, 1, 2, 0, 1, 2].iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        for (i, name) in ["stand1", "run1", "run2"].iter().enumerate() {
+            [1.0f32, 1.0, 1.0, 0.0, 0.0, i as f32].iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+            let mut padded = [0u8; 16];
+            padded[..name.len()].copy_from_slice(name.as_bytes());
+            bytes.extend(padded);
+            bytes.extend([0, 0, 0, 0, 10, 0, 0, 0, 0, 10, 0, 0]);
+        }
+        return bytes;
+    }
+
+    #[test]
+    fn loads_frames_and_clips() {
+        let mesh = parse(&model()).unwrap();
+        assert_eq!(mesh.triangles.len(), 1);
+        // Winding flipped, Z up turned into Y up
+        let pos: Vec<Vec3> = mesh.triangles[0].v.iter().map(|v| v.pos).collect();
+        assert_eq!(pos, [Vec3::ZERO, Vec3::new(0.0, 0.0, -10.0), Vec3::new(10.0, 0.0, 0.0)]);
+        assert_eq!(mesh.triangles[0].v[1].texture, Vec2::new(0.0, 0.0));
+        assert_eq!(mesh.triangles[0].v[2].texture, Vec2::new(1.0, 1.0));
+
+        let names: Vec<&str> = mesh.morph_targets.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["stand1", "run1", "run2"]);
+        assert_eq!(mesh.morph_targets[2].deltas[0].v[1].pos, Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(mesh.frame_clip("stand").unwrap().frames, [0]);
+        assert_eq!(mesh.frame_clip("run").unwrap().frames, [1, 2]);
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        return parse(bytes).err().expect("loaded");
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(error(b"IDP2"), "truncated header");
+        assert_eq!(error(b"IDP3"), "not an MD2 version 8 file");
+        let mut bytes = model();
+        bytes[4] = 7;
+        assert_eq!(error(&bytes), "not an MD2 version 8 file");
+        assert_eq!(error(&model()[..40]), "truncated header");
+        assert_eq!(error(&model()[..120]), "truncated frames");
+        let mut bytes = model();
+        bytes[80] = 3;
+        assert_eq!(error(&bytes), "triangle 0: index out of range");
+        let mut bytes = model();
+        bytes[40] = 0;
+        assert_eq!(error(&bytes), "no frames or no triangles");
+        let mut bytes = model();
+        bytes[24..28].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(error(&bytes), "negative value in the header");
+    }
+}
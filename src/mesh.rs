@@ -1,33 +1,58 @@
-use glam::{Vec3, Vec2};
+use glam::{Vec3, Vec2, Vec4};
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
 
-const COLOR: u32 = 0xFF2020FF;
-//const COLOR: u32 = 0xFFB0B0B0;
+use crate::color::Color;
+use crate::bounds::{Aabb, Sphere};
+use crate::bvh::Bvh;
+use crate::animation::FrameClip;
+use crate::gltf::load_gltf;
+use crate::md2::load_md2;
+use crate::skeleton::{Skeleton, SkeletonClip};
+
+const COLOR: Color = Color::rgb(1.0, 0.125, 0.125);
+//const COLOR: Color = Color::rgb(0.69, 0.69, 0.69);
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Vertex {
     pub pos: Vec3,
     pub normal: Vec3,
     pub texture: Vec2,
-    pub color: u32,
-    pub lit: f32,
+    pub color: Color,
+    pub tangent: Vec4,  // w is the bitangent sign, bitangent = cross(normal, tangent) * w
+    pub joints: [u16; 4],   // Skeleton joints moving the vertex, see Skeleton
+    pub weights: Vec4,      // Joint weights, all zero for rigid meshes
 }
 impl Vertex {
-    pub fn new(pos: Vec3, normal: Vec3, texture: Vec2, color: u32, lit: f32) -> Self {
-        Self { pos, normal, texture, color, lit }
+    pub fn new(pos: Vec3, normal: Vec3, texture: Vec2, color: Color) -> Self {
+        Self { pos, normal, texture, color, tangent: Vec4::ZERO, joints: [0; 4], weights: Vec4::ZERO }
+    }
+    pub fn lerp(&self, other: &Vertex, t: f32) -> Self {
+        Self {
+            pos: self.pos.lerp(other.pos, t),
+            normal: self.normal.lerp(other.normal, t),
+            texture: self.texture.lerp(other.texture, t),
+            color: self.color.lerp(other.color, t),
+            tangent: self.tangent.lerp(other.tangent, t),
+            joints: self.joints,
+            weights: self.weights.lerp(other.weights, t),
+        }
     }
 }
 
+// Mesh triangles use Vertex, the pipeline uses shader output (RasterVertex)
 #[derive(Clone, Debug)]
-pub struct Triangle {
-    pub v: [Vertex; 3],
+pub struct Triangle<V = Vertex> {
+    pub v: [V; 3],
 }
-impl Triangle {
+impl<V> Triangle<V> {
     pub fn new(
-        p1: Vertex,
-        p2: Vertex,
-        p3: Vertex,
+        p1: V,
+        p2: V,
+        p3: V,
     ) -> Self {
         Self {
             v: [p1, p2, p3]
@@ -35,121 +60,344 @@ pub fn new(
     }
 }
 
+// Offset of one vertex in a morph target
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MorphDelta {
+    pub pos: Vec3,
+    pub normal: Vec3,
+}
+
+// Alternative shape of a mesh, one delta per triangle corner
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    pub name: String,
+    pub weight: f32,    // Used when the model doesn't set one
+    pub deltas: Vec<Triangle<MorphDelta>>,
+}
+impl MorphTarget {
+    // Same shape as the mesh, fill in the deltas
+    pub fn new(name: &str, weight: f32, triangles: usize) -> Self {
+        let zero = Triangle::new(MorphDelta::default(), MorphDelta::default(), MorphDelta::default());
+        Self { name: name.to_string(), weight, deltas: vec![zero; triangles] }
+    }
+}
+
 pub struct Mesh {
     pub triangles: Vec<Triangle>,
+    pub aabb: Aabb,     // Model space, see compute_bounds
+    pub sphere: Sphere,
+    pub bvh: Bvh,       // For ray picking, see build_bvh
+    pub skeleton: Option<Skeleton>,     // Skinned meshes, vertices are in the bind pose
+    pub clips: Vec<Rc<SkeletonClip>>,   // Animations that came with the skeleton
+    pub morph_targets: Vec<MorphTarget>,    // See Model::morph_weights
+    pub frame_clips: Vec<Rc<FrameClip>>,    // Vertex animations of the morph targets
 }
 impl Mesh {
     pub fn new() -> Self {
         Self {
             triangles: Vec::new(),
+            aabb: Aabb::default(),
+            sphere: Sphere::default(),
+            bvh: Bvh::default(),
+            skeleton: None,
+            clips: Vec::new(),
+            morph_targets: Vec::new(),
+            frame_clips: Vec::new(),
+        }
+    }
+    // Picks the loader from the extension, .gltf and .glb, .md2 or anything else as .obj
+    // Paths with # are numbered .obj sequences, see from_obj_sequence
+    pub fn load(path: &str) -> Result<Self, String> {
+        if path.ends_with(".gltf") || path.ends_with(".glb") {
+            return load_gltf(path);
         }
+        if path.ends_with(".md2") {
+            return load_md2(path);
+        }
+        if path.contains('#') {
+            return Self::from_obj_sequence(path);
+        }
+        return Self::from_obj(path);
+    }
+    pub fn clip(&self, name: &str) -> Option<Rc<SkeletonClip>> {
+        return self.clips.iter().find(|c| c.name == name).cloned();
+    }
+    pub fn frame_clip(&self, name: &str) -> Option<Rc<FrameClip>> {
+        return self.frame_clips.iter().find(|c| c.name == name).cloned();
     }
-    // TODO: Rewrite this
-    pub fn from_obj(path: &str) -> Self {
+    // Frames 1, 2, 3.. (or 0, 1, 2..) of a pattern like "run_##.obj", #s are replaced by the zero
+    // padded frame number. The frames become one clip named after the pattern ("run")
+    pub fn from_obj_sequence(pattern: &str) -> Result<Self, String> {
+        let width = pattern.matches('#').count();
+        let hashes = "#".repeat(width);
+        if !pattern.contains(&hashes) {
+            return Err(format!("{}: #s have to be next to each other", pattern));
+        }
+        let frame = |i: usize| pattern.replace(&hashes, &format!("{:0width$}", i, width = width));
+        let first = if Path::new(&frame(0)).exists() { 0 } else { 1 };
+        let paths: Vec<String> = (first..).map(frame).take_while(|p| Path::new(p).exists()).collect();
+        if paths.is_empty() {
+            return Err(format!("{}: no frames found", pattern));
+        }
+        let stem = Path::new(pattern).file_stem().and_then(|s| s.to_str()).unwrap_or(pattern);
+        let name = stem.replace('#', "").trim_end_matches(['_', '-', '.']).to_string();
+        return Self::from_obj_frames(&name, &paths);
+    }
+    // Vertex animation from .obj files with the same triangles, the first one is the rest shape
+    // Every frame is a morph target named after its file, played by a clip at 10 frames per second
+    pub fn from_obj_frames(name: &str, paths: &[impl AsRef<str>]) -> Result<Self, String> {
+        let Some(first) = paths.first().map(AsRef::as_ref) else { return Err(format!("{}: no frames", name)) };
+        let mut mesh = Self::from_obj(first)?;
+        mesh.morph_targets.push(MorphTarget::new(first, 0.0, mesh.triangles.len()));
+        for path in paths[1..].iter().map(AsRef::as_ref) {
+            let frame = Self::from_obj(path)?;
+            mesh.add_morph_target(path, &frame).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        mesh.frame_clips.push(Rc::new(FrameClip::new(name, (0..paths.len()).collect(), 10.0)));
+        return Ok(mesh);
+    }
+    pub fn morph_target(&self, name: &str) -> Option<usize> {
+        return self.morph_targets.iter().position(|t| t.name == name);
+    }
+    // Adds the difference to another mesh with the same triangles (like an OBJ exported in another pose)
+    pub fn add_morph_target(&mut self, name: &str, shape: &Mesh) -> Result<(), String> {
+        if shape.triangles.len() != self.triangles.len() {
+            return Err(format!("morph target \"{}\" has {} triangles, the mesh has {}", name, shape.triangles.len(), self.triangles.len()));
+        }
+        let deltas = self.triangles.iter().zip(shape.triangles.iter()).map(|(a, b)| {
+            let d = |i: usize| MorphDelta { pos: b.v[i].pos - a.v[i].pos, normal: b.v[i].normal - a.v[i].normal };
+            Triangle::new(d(0), d(1), d(2))
+        }).collect();
+        self.morph_targets.push(MorphTarget { name: name.to_string(), weight: 0.0, deltas });
+        return Ok(());
+    }
+    // Blends the morph targets into out's triangles (reusing its buffer), false if every weight is 0
+    // Weights index morph_targets, missing ones use the target's own weight
+    pub fn morph_into(&self, weights: &[f32], out: &mut Mesh) -> bool {
+        let weights: Vec<f32> = self.morph_targets.iter().enumerate().map(|(i, t)| weights.get(i).copied().unwrap_or(t.weight)).collect();
+        if weights.iter().all(|w| *w == 0.0) {
+            return false;
+        }
+        out.triangles.clone_from(&self.triangles);
+        for (target, w) in self.morph_targets.iter().zip(weights).filter(|(_, w)| *w != 0.0) {
+            for (tri, deltas) in out.triangles.iter_mut().zip(target.deltas.iter()) {
+                for (v, d) in tri.v.iter_mut().zip(deltas.v.iter()) {
+                    v.pos += d.pos * w;
+                    v.normal += d.normal * w;
+                }
+            }
+        }
+        out.compute_bounds();
+        return true;
+    }
+    // Bad files return an error with the line number
+    pub fn from_obj(path: &str) -> Result<Self, String> {
         let mut model = Self::new();
         let mut pos = Vec::<Vec3>::new();
         let mut norm = Vec::<Vec3>::new();
         let mut tex = Vec::<Vec2>::new();
 
-        let file = File::open(path).unwrap();
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
         let iter = io::BufReader::new(file).lines();
-        for line in iter {
-            let line = line.unwrap();
+        for (n, line) in iter.enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", path, e))?;
+            let error = |what: &str| format!("{}:{}: {}", path, n + 1, what);
+            let values = line
+                .split(" ")
+                .filter_map(|s| s.parse::<f32>().ok())
+                .collect::<Vec<_>>();
 
             if line.starts_with("vn") {
                 // Vertex normals
-                let normal = line
-                    .split(" ")
-                    .filter_map(|s| s.parse::<f32>().ok())
-                    .collect::<Vec<_>>();
-                norm.push(Vec3 {
-                    x: normal[0],
-                    y: normal[1],
-                    z: normal[2],
-                });
+                if values.len() < 3 {
+                    return Err(error("normal needs 3 values"));
+                }
+                norm.push(Vec3::new(values[0], values[1], values[2]));
             } else if line.starts_with("vt") {
                 // Texture
-                let texture = line
-                    .split(" ")
-                    .filter_map(|s| s.parse::<f32>().ok())
-                    .collect::<Vec<_>>();
-                tex.push(Vec2 {
-                    x: texture[0],
-                    y: texture[1],
-                });
+                if values.len() < 2 {
+                    return Err(error("texture coordinate needs 2 values"));
+                }
+                tex.push(Vec2::new(values[0], values[1]));
             } else if line.starts_with("v") {
                 // Vertex pos
-                let vertex = line
-                    .split(" ")
-                    .filter_map(|s| s.parse::<f32>().ok())
-                    .collect::<Vec<_>>();
-                pos.push(Vec3 {
-                    x: vertex[0],
-                    y: vertex[1],
-                    z: vertex[2],
-                });
+                if values.len() < 3 {
+                    return Err(error("vertex needs 3 values"));
+                }
+                pos.push(Vec3::new(values[0], values[1], values[2]));
             } else if line.starts_with("f") {
-                // FaceIndex/TextureIndex/NormalIndex
+                // FaceIndex/TextureIndex/NormalIndex, non textured meshes have no TextureIndex
                 let f = line
                     .split([' ', '/'].as_ref())
                     .filter_map(|s| s.parse::<u32>().ok())
                     .collect::<Vec<_>>();
-                //println!("{}", line);
-
-                if tex.len() == 0 {
-                    // Non textured mesh
-                    model.triangles.push(Triangle::new(
-                        Vertex {
-                            pos: (pos[(f[0] - 1) as usize]),
-                            normal: (norm[(f[1] - 1) as usize]),
-                            texture: Vec2::new(-1.0, -1.0),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                        Vertex {
-                            pos: (pos[(f[2] - 1) as usize]),
-                            normal: (norm[(f[3] - 1) as usize]),
-                            texture: Vec2::new(-1.0, -1.0),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                        Vertex {
-                            pos: (pos[(f[4] - 1) as usize]),
-                            normal: (norm[(f[5] - 1) as usize]),
-                            texture: Vec2::new(-1.0, -1.0),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                    ));
+                let stride = if tex.is_empty() { 2 } else { 3 };
+                if f.len() < stride * 3 {
+                    return Err(error("face needs 3 vertices with normals"));
+                }
+
+                let mut v = [Vertex::default(); 3];
+                for (i, vertex) in v.iter_mut().enumerate() {
+                    let f = &f[i * stride..];
+                    let p = index(&pos, f[0]).ok_or_else(|| error("vertex index out of range"))?;
+                    let normal = index(&norm, f[stride - 1]).ok_or_else(|| error("normal index out of range"))?;
+                    *vertex = if stride == 2 {
+                        Vertex::new(p, normal, Vec2::new(-1.0, -1.0), COLOR)
+                    } else {
+                        let t = index(&tex, f[1]).ok_or_else(|| error("texture index out of range"))?;
+                        Vertex::new(p, normal, t, Color::WHITE)
+                    };
+                }
+                model.triangles.push(Triangle::new(v[0], v[1], v[2]));
+            }
+        }
+        if !tex.is_empty() {
+            model.generate_tangents();
+        }
+        model.compute_bounds();
+        model.build_bvh();
+        return Ok(model);
+    }
+    // Untextured meshes (uv of -1 like from_obj gives them) are written without texture coordinates
+    pub fn save_obj(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let textured = self.triangles.iter().any(|t| t.v[0].texture != Vec2::new(-1.0, -1.0));
+        let mut pos: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut norm: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut tex: HashMap<[u32; 2], usize> = HashMap::new();
+
+        for tri in self.triangles.iter() {
+            let mut face = String::from("f");
+            for v in tri.v.iter() {
+                let count = pos.len();
+                let p = *pos.entry(v.pos.to_array().map(f32::to_bits)).or_insert(count + 1);
+                if p > count {
+                    writeln!(file, "v {} {} {}", v.pos.x, v.pos.y, v.pos.z)?;
+                }
+                let count = norm.len();
+                let n = *norm.entry(v.normal.to_array().map(f32::to_bits)).or_insert(count + 1);
+                if n > count {
+                    writeln!(file, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z)?;
+                }
+                if textured {
+                    let count = tex.len();
+                    let t = *tex.entry(v.texture.to_array().map(f32::to_bits)).or_insert(count + 1);
+                    if t > count {
+                        writeln!(file, "vt {} {}", v.texture.x, v.texture.y)?;
+                    }
+                    face += &format!(" {}/{}/{}", p, t, n);
                 } else {
-                    // Textured mesh
-                    model.triangles.push(Triangle::new(
-                        Vertex {
-                            pos: (pos[(f[0] - 1) as usize]),
-                            texture: (tex[(f[1] - 1) as usize]),
-                            normal: (norm[(f[2] - 1) as usize]),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                        Vertex {
-                            pos: (pos[(f[3] - 1) as usize]),
-                            texture: (tex[(f[4] - 1) as usize]),
-                            normal: (norm[(f[5] - 1) as usize]),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                        Vertex {
-                            pos: (pos[(f[6] - 1) as usize]),
-                            texture: (tex[(f[7] - 1) as usize]),
-                            normal: (norm[(f[8] - 1) as usize]),
-                            color: (COLOR),
-                            lit: 0.0
-                        },
-                    ));
+                    face += &format!(" {}//{}", p, n);
                 }
-                //println!("{} {} {}", tex[(f[1] - 1) as usize], tex[(f[4] - 1) as usize], tex[(f[7] - 1) as usize]);
             }
+            writeln!(file, "{}", face)?;
+        }
+        return file.flush();
+    }
+    // Both of these are derived from the vertex positions, call them again after changing those
+    // Ray picking tree
+    pub fn build_bvh(&mut self) {
+        self.bvh = Bvh::new(&self.triangles);
+    }
+    // Box and sphere used for culling
+    pub fn compute_bounds(&mut self) {
+        let points = self.triangles.iter().flat_map(|t| t.v.iter().map(|v| v.pos));
+        self.aabb = Aabb::from_points(points.clone());
+        self.sphere = Sphere::from_points(points);
+    }
+    // Per vertex tangents from the texture coordinates: each face's uv derivatives weighted by
+    // the corner angle, summed over vertices with the same position, normal and uv, then
+    // orthogonalized against the normal with the bitangent sign in w. Close to MikkTSpace on
+    // smooth meshes, but not the same algorithm, baked normal maps can show small differences
+    pub fn generate_tangents(&mut self) {
+        let key = |v: &Vertex| {
+            [v.pos.x, v.pos.y, v.pos.z, v.normal.x, v.normal.y, v.normal.z, v.texture.x, v.texture.y].map(f32::to_bits)
+        };
+        let mut sums: HashMap<[u32; 8], (Vec3, Vec3)> = HashMap::new();
+
+        for tri in self.triangles.iter() {
+            let (p, t) = (tri.v.map(|v| v.pos), tri.v.map(|v| v.texture));
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (d1, d2) = (t[1] - t[0], t[2] - t[0]);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (e1 * d2.y - e2 * d1.y) / det;
+            let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+
+            for i in 0..3 {
+                // Weight by the angle of the corner
+                let a = (p[(i + 1) % 3] - p[i]).normalize_or_zero();
+                let b = (p[(i + 2) % 3] - p[i]).normalize_or_zero();
+                let angle = a.dot(b).clamp(-1.0, 1.0).acos();
+
+                let sum = sums.entry(key(&tri.v[i])).or_insert((Vec3::ZERO, Vec3::ZERO));
+                sum.0 += tangent * angle;
+                sum.1 += bitangent * angle;
+            }
+        }
+
+        for tri in self.triangles.iter_mut() {
+            for v in tri.v.iter_mut() {
+                let Some((t, b)) = sums.get(&key(v)) else { continue };
+                let n = v.normal.normalize_or_zero();
+                let tangent = (*t - n * n.dot(*t)).normalize_or_zero();
+                let sign = if n.cross(tangent).dot(*b) < 0.0 { -1.0 } else { 1.0 };
+                v.tangent = tangent.extend(sign);
+            }
+        }
+    }
+}
+
+// Obj indices start at 1
+fn index<T: Copy>(list: &[T], i: u32) -> Option<T> {
+    return list.get((i as usize).checked_sub(1)?).copied();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Copies the models into a fresh directory as the given file names
+    fn frames(dir: &str, files: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("drawing_{}_{}", dir, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (model, name) in files {
+            fs::copy(format!("models/{}", model), dir.join(name)).unwrap();
         }
-        return model;
+        return dir.to_str().unwrap().to_string();
+    }
+
+    #[test]
+    fn obj_sequence_becomes_a_clip() {
+        let dir = frames("sequence", &[("arctic.obj", "run_01.obj"), ("arctic_run.obj", "run_02.obj"), ("arctic.obj", "run_03.obj")]);
+        let mesh = Mesh::from_obj_sequence(&format!("{}/run_##.obj", dir)).unwrap();
+        let run = Mesh::from_obj("models/arctic_run.obj").unwrap();
+        assert_eq!(mesh.morph_targets.len(), 3);
+        assert_eq!(mesh.frame_clip("run").unwrap().frames, [0, 1, 2]);
+
+        // Frame 2 fully weighted is the second file
+        let mut posed = Mesh::new();
+        assert!(mesh.morph_into(&[0.0, 1.0, 0.0], &mut posed));
+        for (a, b) in posed.triangles.iter().zip(run.triangles.iter()) {
+            assert!(a.v.iter().zip(b.v.iter()).all(|(a, b)| a.pos.abs_diff_eq(b.pos, 1e-5)));
+        }
+        assert!(!mesh.morph_into(&[0.0; 3], &mut posed));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn obj_sequence_errors() {
+        let dir = frames("mismatch", &[("arctic.obj", "walk0.obj"), ("cube.obj", "walk1.obj")]);
+        let error = Mesh::from_obj_sequence(&format!("{}/walk#.obj", dir)).err().expect("loaded");
+        assert!(error.starts_with(&format!("{}/walk1.obj: morph target", dir)), "{}", error);
+        assert!(error.ends_with("has 12 triangles, the mesh has 721"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(Mesh::from_obj_sequence("run_#_#.obj").err().expect("loaded"), "run_#_#.obj: #s have to be next to each other");
+        assert_eq!(Mesh::from_obj_sequence("missing_#.obj").err().expect("loaded"), "missing_#.obj: no frames found");
+        assert_eq!(Mesh::from_obj_frames("none", &[] as &[&str]).err().expect("loaded"), "none: no frames");
     }
 }
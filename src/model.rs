@@ -1,25 +1,90 @@
 use glam::{Vec3, Quat, Mat4};
 
-use crate::mesh::*;
+use crate::animation::{Animation, FrameClip};
+use crate::skeleton::SkeletonClip;
+use crate::assets::{MeshHandle, TextureHandle};
+use crate::material::Material;
+
+// Lower detail mesh, used once the model covers less than screen_size of the screen height
+#[derive(Clone, Copy, Debug)]
+pub struct Lod {
+    pub mesh: MeshHandle,
+    pub screen_size: f32,
+}
 
 pub struct Model {
-    pub mesh: Mesh,
+    pub mesh: MeshHandle,
     pub translation: Vec3,
     pub scale: Vec3,
     pub rotation: Quat,
-    pub texture_index: i32,
+    pub texture: Option<TextureHandle>,   // None uses the vertex colors
+    pub material: Material,
+    pub lods: Vec<Lod>,     // Sorted from most to least detailed, see Renderer::update_lod
+    pub lod: usize,         // 0 is mesh, n is lods[n - 1]
+    pub lod_fade: Option<(usize, f32)>,     // Level faded out from and how far along the fade is
+    pub animation: Option<Animation>,   // Drives the transform, see animate
+    pub skeleton_animation: Option<Animation<SkeletonClip>>,   // Poses skinned meshes, see Renderer::pose
+    pub morph_weights: Vec<f32>,    // One per Mesh::morph_targets, set every frame to animate
+    pub vertex_animation: Option<Animation<FrameClip>>,     // Overwrites morph_weights in animate
 }
 impl Model {
-    pub fn new(path: &str, texture_index: i32) -> Self {
+    pub fn new(mesh: MeshHandle, texture: Option<TextureHandle>) -> Self {
         Self {
-            mesh: Mesh::from_obj(path),
+            mesh,
             translation: Vec3::ZERO,
             scale: Vec3::splat(1.0),
             rotation: Quat::IDENTITY,
-            texture_index
+            texture,
+            material: Material::default(),
+            lods: Vec::new(),
+            lod: 0,
+            lod_fade: None,
+            animation: None,
+            skeleton_animation: None,
+            morph_weights: Vec::new(),
+            vertex_animation: None,
+        }
+    }
+    // Advances the animations by dt seconds and applies the transform one
+    pub fn animate(&mut self, dt: f32) {
+        if let Some(animation) = self.skeleton_animation.as_mut() {
+            animation.update(dt);
+        }
+        if let Some(animation) = self.vertex_animation.as_mut() {
+            animation.update(dt);
+            self.morph_weights = animation.morph_weights();
         }
+        let Some(animation) = self.animation.as_mut() else { return };
+        animation.update(dt);
+        animation.apply(&mut self.translation, &mut self.rotation, &mut self.scale);
+    }
+    // Levels past the end of lods (shortened since they were picked) use the coarsest one left
+    pub fn lod_mesh(&self, level: usize) -> MeshHandle {
+        return match level.min(self.lods.len()) {
+            0 => self.mesh,
+            level => self.lods[level - 1].mesh,
+        };
     }
     pub fn get_model_mat(&self) -> Mat4 {
         return Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::mesh::Mesh;
+
+    #[test]
+    fn lod_past_the_end_uses_the_coarsest_level() {
+        let mut assets = Assets::new();
+        let (full, half) = (assets.add_mesh("full", Mesh::new()), assets.add_mesh("half", Mesh::new()));
+        let mut model = Model::new(full, None);
+        model.lods.push(Lod { mesh: half, screen_size: 0.3 });
+        (model.lod, model.lod_fade) = (3, Some((2, 0.5)));
+        assert_eq!(model.lod_mesh(model.lod), half);
+        model.lods.clear();
+        assert_eq!(model.lod_mesh(model.lod), full);
+    }
+}
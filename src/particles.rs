@@ -0,0 +1,136 @@
+use glam::{Vec2, Vec3, Mat4};
+
+use crate::assets::{Texture, TextureHandle};
+use crate::color::{Color, BlendMode};
+use crate::mesh::{Mesh, Triangle, Vertex};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub pos: Vec3,
+    pub velocity: Vec3,
+    pub age: f32,
+    pub lifetime: f32,
+}
+impl Particle {
+    // 0 when spawned, 1 when it dies
+    pub fn life(&self) -> f32 {
+        return (self.age / self.lifetime).clamp(0.0, 1.0);
+    }
+}
+
+// Spawns particles at a steady rate and moves them, see Renderer::draw_particles
+// Particles live in world space, moving the emitter doesn't drag the old ones along
+pub struct Emitter {
+    pub pos: Vec3,
+    pub area: Vec3,     // Half size of the box particles spawn in, zero for a point
+    pub rate: f32,      // Particles per second
+    pub lifetime: (f32, f32),   // Random between the two, in seconds
+    pub velocity: Vec3,
+    pub spread: f32,    // Random velocity in any direction added on top, up to this fast
+    pub gravity: Vec3,
+    pub color: (Color, Color),  // At the start and the end of the life, alpha fades too
+    pub size: (f32, f32),       // Same, world space width of the quad
+    pub texture: Option<TextureHandle>,     // Untextured particles are plain squares
+    pub blend: BlendMode,
+    pub max_particles: usize,
+    pub emitting: bool,     // Live particles keep moving when it's off
+    pub particles: Vec<Particle>,
+    spawn: f32,     // Part of a particle left over from the last update
+    seed: u32,
+}
+impl Emitter {
+    pub fn new(pos: Vec3, rate: f32) -> Self {
+        Self {
+            pos,
+            area: Vec3::ZERO,
+            rate,
+            lifetime: (1.0, 1.0),
+            velocity: Vec3::ZERO,
+            spread: 0.0,
+            gravity: Vec3::ZERO,
+            color: (Color::WHITE, Color::new(1.0, 1.0, 1.0, 0.0)),
+            size: (0.1, 0.1),
+            texture: None,
+            blend: BlendMode::Alpha,
+            max_particles: 1000,
+            emitting: true,
+            particles: Vec::new(),
+            spawn: 0.0,
+            seed: 0x9E3779B9,
+        }
+    }
+    pub fn update(&mut self, dt: f32) {
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            p.velocity += self.gravity * dt;
+            p.pos += p.velocity * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if !self.emitting {
+            self.spawn = 0.0;
+            return;
+        }
+        self.spawn += self.rate * dt;
+        let count = self.spawn as usize;
+        self.spawn -= count as f32;
+        self.burst(count);
+    }
+    // Spawns count particles right away, up to max_particles
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count.min(self.max_particles.saturating_sub(self.particles.len())) {
+            let offset = Vec3::new(self.random() * 2.0 - 1.0, self.random() * 2.0 - 1.0, self.random() * 2.0 - 1.0);
+            let lifetime = self.lifetime.0 + (self.lifetime.1 - self.lifetime.0) * self.random();
+            let velocity = self.velocity + self.random_direction() * self.spread * self.random();
+            self.particles.push(Particle { pos: self.pos + offset * self.area, velocity, age: 0.0, lifetime: lifetime.max(f32::EPSILON) });
+        }
+    }
+    // Camera facing quads in world space, sorted back to front for alpha blending
+    pub fn mesh(&self, mat_view: Mat4) -> Mesh {
+        let (right, up) = (mat_view.row(0).truncate(), mat_view.row(1).truncate());
+        let camera_pos = mat_view.inverse().transform_point3(Vec3::ZERO);
+        let normal = up.cross(right);
+        let mut sorted: Vec<&Particle> = self.particles.iter().collect();
+        sorted.sort_by(|a, b| b.pos.distance_squared(camera_pos).total_cmp(&a.pos.distance_squared(camera_pos)));
+
+        let mut mesh = Mesh::new();
+        for p in sorted {
+            let t = p.life();
+            let color = self.color.0.lerp(self.color.1, t);
+            let half = (self.size.0 + (self.size.1 - self.size.0) * t) * 0.5;
+            let corner = |x: f32, y: f32| Vertex::new(p.pos + (right * x + up * y) * half, normal, Vec2::new(x * 0.5 + 0.5, y * 0.5 + 0.5), color);
+            mesh.triangles.push(Triangle::new(corner(-1.0, -1.0), corner(-1.0, 1.0), corner(1.0, 1.0)));
+            mesh.triangles.push(Triangle::new(corner(-1.0, -1.0), corner(1.0, 1.0), corner(1.0, -1.0)));
+        }
+        return mesh;
+    }
+
+    // Xorshift, 0..1
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        return (self.seed >> 8) as f32 / (1 << 24) as f32;
+    }
+    fn random_direction(&mut self) -> Vec3 {
+        loop {
+            let v = Vec3::new(self.random(), self.random(), self.random()) * 2.0 - Vec3::ONE;
+            if v.length_squared() > 0.0001 && v.length_squared() <= 1.0 {
+                return v.normalize();
+            }
+        }
+    }
+}
+
+// White dot fading out towards the edges, a good default particle texture
+pub fn soft_dot(size: u32) -> Texture {
+    let mut pixels = Vec::with_capacity((size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let d = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / size as f32 * 2.0 - Vec2::ONE;
+            let alpha = (1.0 - d.length()).max(0.0);
+            pixels.push(Color::new(1.0, 1.0, 1.0, alpha * alpha).to_u32());
+        }
+    }
+    return (pixels, size, size);
+}
@@ -0,0 +1,72 @@
+use crate::canvas::{Canvas, RenderTarget, HEIGHT, WIDTH};
+use crate::color::{Color, BlendMode};
+use crate::assets::{Texture, TextureHandle};
+
+// Off-screen target, drawn into like the main canvas, then Renderer::resolve copies
+// every color buffer into its texture (mirrors, monitors, portals)
+pub struct RenderTexture {
+    pub canvas: Canvas,
+    pub attachments: Vec<Box<[u32]>>,   // Extra color buffers written through target()
+    pub textures: Vec<TextureHandle>,     // Canvas first, then one per attachment
+    pub downscale: usize,       // Textures are WIDTH / downscale by HEIGHT / downscale
+}
+impl RenderTexture {
+    pub fn new(attachments: usize, textures: Vec<TextureHandle>, downscale: usize) -> Self {
+        Self {
+            canvas: Canvas::new(),
+            attachments: (0..attachments).map(|_| vec![0u32; WIDTH * HEIGHT].into_boxed_slice()).collect(),
+            textures,
+            downscale: downscale.max(1),
+        }
+    }
+    pub fn clear(&mut self, color: Color) {
+        self.canvas.clear(color);
+        for pixels in self.attachments.iter_mut() {
+            pixels.fill(color.to_u32());
+        }
+    }
+    pub fn target(&mut self) -> MultiTarget<'_> {
+        return MultiTarget { canvas: &mut self.canvas, attachments: &mut self.attachments };
+    }
+}
+
+// Multiple render targets, output N goes to attachment N - 1, output 0 to the canvas
+// All of them share the canvas depth buffer
+pub struct MultiTarget<'a> {
+    pub canvas: &'a mut Canvas,
+    pub attachments: &'a mut [Box<[u32]>],
+}
+impl<'a, const N: usize> RenderTarget<[Color; N]> for MultiTarget<'a> {
+    fn canvas(&mut self) -> &mut Canvas {
+        return self.canvas;
+    }
+    fn write(&mut self, x: i32, y: i32, z: f32, out: [Color; N], blend: BlendMode) {
+        if N == 0 || z <= self.canvas.get_depth(x, y) {
+            return;
+        }
+        let i = (x + y * WIDTH as i32) as usize;
+        for (pixels, color) in self.attachments.iter_mut().zip(out.iter().skip(1)) {
+            pixels[i] = color.blend(Color::from_u32(pixels[i]), blend).to_u32();
+        }
+        self.canvas.blend_pixel(x, y, z, out[0], blend);
+    }
+}
+
+// Box filters a canvas sized buffer into a texture
+// Rows are flipped to match the textures converted by tex.sh
+pub fn downsample(pixels: &[u32], factor: usize) -> Texture {
+    let (w, h) = (WIDTH / factor, HEIGHT / factor);
+    let mut texture = Vec::<u32>::with_capacity(w * h);
+    for y in (0..h).rev() {
+        for x in 0..w {
+            let mut sum = Color::splat(0.0);
+            for sy in 0..factor {
+                for sx in 0..factor {
+                    sum += Color::from_u32(pixels[(x * factor + sx) + (y * factor + sy) * WIDTH]);
+                }
+            }
+            texture.push((sum * (1.0 / (factor * factor) as f32)).to_u32());
+        }
+    }
+    return (texture, w as u32, h as u32);
+}
@@ -1,79 +1,465 @@
-use glam::{Mat4, Vec3, Vec4Swizzles, Vec3Swizzles, Mat3};
-use std::collections::{VecDeque, HashMap};
+use glam::{Mat4, Vec3, Vec3Swizzles, Mat3};
+use std::collections::VecDeque;
+use std::rc::Rc;
 
-use crate::{mesh::{Triangle, Vertex}, model::Model, clipping::clip_triangle, canvas::{Canvas, HEIGHT, WIDTH}, utils::*, camera::Camera, shapes::*, shapes_textured::draw_triangle_tex};
+use crate::{mesh::{Mesh, Triangle}, model::Model, clipping::clip_triangle, canvas::{Canvas, RenderTarget, HEIGHT, WIDTH}, camera::{Camera, Projection}, shapes::*, shapes_textured::draw_triangle_tex};
+use crate::lighting::{Light, ShadingMode};
+use crate::fog::Fog;
+use crate::color::{Color, BlendMode};
+use crate::shader::*;
+use crate::gbuffer::{GBuffer, GBufferShader, GBufferTarget};
+use crate::cubemap::CubeMap;
+use crate::render_texture::{RenderTexture, downsample};
+use crate::assets::{Assets, MeshHandle, Texture, TextureHandle};
+use crate::material::Material;
+use crate::scene::Scene;
+use crate::bounds::Frustum;
+use crate::bvh::Hit;
+use crate::skeleton::skin_mesh;
+use crate::particles::Emitter;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortMode {
+    PerModel,       // Models sorted back to front, triangles inside a model keep their order
+    PerTriangle,    // Every triangle sorted back to front
+}
+
+// Models tested against the view frustum and how many were skipped, reset by hand
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+    pub tested: u32,
+    pub culled: u32,
+}
+impl CullStats {
+    fn count(&mut self, visible: bool) {
+        self.tested += 1;
+        if !visible {
+            self.culled += 1;
+        }
+    }
+}
+
+// Result of Renderer::pick
+#[derive(Clone, Copy, Debug)]
+pub struct Pick {
+    pub model: usize,   // Index into the picked models
+    pub world: Vec3,
+    pub hit: Hit,
+}
+
+// A mesh with its texture and material, what draw_instanced repeats
+#[derive(Clone, Copy)]
+pub struct Surface<'a> {
+    pub mesh: MeshHandle,
+    pub texture: Option<TextureHandle>,
+    pub material: &'a Material,
+}
+
+// How a model changes its mesh before the vertex shader (morph weights) and in it (joints)
+#[derive(Clone, Copy, Default)]
+struct Deform<'a> {
+    joints: &'a [Mat4],
+    morph_weights: &'a [f32],
+}
+
+// Mesh with its morph targets blended in, reused by every draw of the frame (LOD fades, shadows)
+struct MorphCache {
+    mesh: MeshHandle,
+    weights: Vec<f32>,
+    fresh: bool,    // Blended this frame, cleared by draw_transparent so changed meshes are picked up
+    morphed: Option<Rc<Mesh>>,  // None when every weight is 0
+}
+
+// Camera and its culling frustum, built once per draw call rather than per mesh
+struct View<'a> {
+    camera: &'a Camera,
+    frustum: Frustum,
+}
+impl<'a> View<'a> {
+    fn new(camera: &'a Camera) -> Self {
+        Self { camera, frustum: Frustum::new(&camera.projection, camera.get_view_mat()) }
+    }
+}
+
+// Triangle waiting for the transparent pass, already projected and in canvas space
+struct TransparentTri {
+    tri: Triangle<RasterVertex<DefaultVaryings>>,
+    texture: Option<TextureHandle>,
+    normal_map: Option<TextureHandle>,
+    blend: BlendMode,
+    alpha_cutoff: Option<f32>,
+    reflectivity: f32,
+    unlit: bool,
+    model_depth: f32,
+}
 
 pub struct Renderer {
-    mat_proj: Mat4,
     pub wireframe: bool,
-    pub textures: HashMap<i32, (Vec<u32>, u32, u32)>,
-    pub tex_num: i32,
+    pub shading: ShadingMode,
+    pub lights: Vec<Light>,
+    pub fog: Fog,
+    pub assets: Assets,
+    pub sort_mode: SortMode,
+    pub environment: Option<CubeMap>,   // Skybox and reflections
+    pub cull_stats: CullStats,
+    pub lod_hysteresis: f32,    // Fraction past a screen size threshold before switching back
+    pub lod_fade_time: f32,     // Seconds to blend between levels, 0 switches right away
+    transparent: Vec<TransparentTri>,
+    morph_cache: Vec<MorphCache>,
 }
 impl Renderer {
-    pub fn new(proj: Mat4) -> Self {
+    pub fn new() -> Self {
         Self {
-            mat_proj: proj,
             wireframe: false,
-            textures: HashMap::new(),
-            tex_num: 0
+            shading: ShadingMode::Gouraud,
+            lights: vec![Light::new(Vec3::new(0.0, 0.0, -1.0), Color::WHITE)],
+            fog: Fog::new(Color::BLACK),
+            assets: Assets::new(),
+            sort_mode: SortMode::PerModel,
+            environment: None,
+            cull_stats: CullStats::default(),
+            lod_hysteresis: 0.15,
+            lod_fade_time: 0.0,
+            transparent: Vec::new(),
+            morph_cache: Vec::new(),
         }
     }
+    // Draws with the default shaders
+    // Opaque models are drawn right away, blended ones are queued for draw_transparent
+    // Models with LODs draw their current level, cross faded with the previous one while blending
+    // Skinned meshes are posed by the model's skeleton animation
     pub fn draw(&mut self, model: &Model, camera: &Camera, canvas: &mut Canvas) {
-        //let mut to_clip = Vec::<Triangle>::with_capacity(self.to_render.len());
-        let mut to_clip = Vec::<Triangle>::new();
         let mat_model = model.get_model_mat();
+        let pose = self.pose(model);
+        let deform = Deform { joints: &pose, morph_weights: &model.morph_weights };
+        let view = View::new(camera);
+        let (solid, fading) = lod_levels(model);
+        let surface = |level: usize, material| Surface { mesh: model.lod_mesh(level), texture: model.texture, material };
+        self.draw_deformed(surface(solid, &model.material), mat_model, deform, &view, canvas);
+        if let Some((level, alpha)) = fading {
+            self.draw_deformed(surface(level, &faded(&model.material, alpha)), mat_model, deform, &view, canvas);
+        }
+    }
+    // Skinning matrices for the model's skeleton animation, the rest pose without one
+    // Empty for rigid meshes, pass as DefaultUniforms::joints when drawing with draw_with
+    pub fn pose(&self, model: &Model) -> Vec<Mat4> {
+        let Some(skeleton) = self.assets.mesh(model.mesh).and_then(|m| m.skeleton.as_ref()) else { return Vec::new() };
+        return skeleton.pose(model.skeleton_animation.as_ref().map(|a| (&*a.clip, a.local_time())));
+    }
+    // Picks the model's level of detail from how much of the screen height it covers
+    // Call once per frame before drawing, dt is the frame time in seconds for the fade
+    pub fn update_lod(&self, model: &mut Model, camera: &Camera, dt: f32) {
+        if let Some((from, t)) = model.lod_fade {
+            let t = if self.lod_fade_time > 0.0 { t + dt / self.lod_fade_time } else { 1.0 };
+            model.lod_fade = if t < 1.0 { Some((from, t)) } else { None };
+        }
+        let Some(mesh) = self.assets.mesh(model.mesh) else { return };
+        if model.lods.is_empty() {
+            return;
+        }
+
+        // Projected height of the bounding sphere, fraction of the screen
+        let sphere = mesh.sphere.transform(model.get_model_mat());
+        let center = camera.get_view_mat().transform_point3(sphere.center);
+        if center.z < camera.projection.near() {
+            return;
+        }
+        let mat_proj = camera.projection.matrix();
+        let top = mat_proj.project_point3(center + Vec3::new(0.0, sphere.radius, 0.0));
+        let size = (top.y - mat_proj.project_point3(center).y).abs();
+
+        // Only move past a threshold once the size is clearly on the other side of it
+        let h = self.lod_hysteresis;
+        let coarser = model.lods.iter().filter(|l| l.screen_size * (1.0 - h) > size).count();
+        let finer = model.lods.iter().filter(|l| l.screen_size * (1.0 + h) > size).count();
+        let level = if model.lod < coarser { coarser } else if model.lod > finer { finer } else { model.lod };
+        if level != model.lod {
+            if self.lod_fade_time > 0.0 {
+                model.lod_fade = Some((model.lod, 0.0));
+            }
+            model.lod = level;
+        }
+    }
+    // Posed meshes are culled with a sphere that holds them in any blend of their joints
+    fn draw_deformed(&mut self, surface: Surface, mat_model: Mat4, deform: Deform, view: &View, canvas: &mut Canvas) {
+        let Surface { mesh, texture, material } = surface;
+        let morphed = self.morphed(mesh, deform.morph_weights);
+        let Some(mesh) = morphed.as_deref().or(self.assets.mesh(mesh)) else { return };
+        let visible = in_frustum(mesh, deform.joints, mat_model, &view.frustum);
+        self.cull_stats.count(visible);
+        if !visible {
+            return;
+        }
+        let uniforms = DefaultUniforms { joints: deform.joints, ..self.default_uniforms(mat_model, texture, material, view.camera.get_view_mat()) };
+        let to_clip = self.transform(mesh, &DefaultVertexShader, &uniforms, &view.camera.projection);
+
+        if material.blend == BlendMode::Opaque {
+            self.rasterize(&to_clip, &DefaultFragmentShader, &uniforms, BlendMode::Opaque, canvas);
+            return;
+        }
+        self.queue_transparent(to_clip, texture, material);
+    }
+    // Blended triangles wait for draw_transparent, sorted as one model
+    fn queue_transparent(&mut self, to_clip: Vec<Triangle<RasterVertex<DefaultVaryings>>>, texture: Option<TextureHandle>, material: &Material) {
+        let model_depth = to_clip.iter().map(tri_depth).sum::<f32>() / to_clip.len().max(1) as f32;
+        for tri in to_clip {
+            self.transparent.push(TransparentTri {
+                tri,
+                texture,
+                normal_map: material.normal_map,
+                blend: material.blend,
+                alpha_cutoff: material.alpha_cutoff,
+                reflectivity: material.reflectivity,
+                unlit: material.unlit,
+                model_depth,
+            });
+        }
+    }
+    // Unlit camera facing quads, blended ones join the transparent pass like any other model
+    // (the emitter sorts its own particles) and are depth tested against what's drawn before it
+    pub fn draw_particles(&mut self, emitter: &Emitter, camera: &Camera, canvas: &mut Canvas) {
+        if emitter.particles.is_empty() {
+            return;
+        }
+        let mat_view = camera.get_view_mat();
+        let mesh = emitter.mesh(mat_view);
+        let material = Material { blend: emitter.blend, unlit: true, ..Material::default() };
+        let uniforms = self.default_uniforms(Mat4::IDENTITY, emitter.texture, &material, mat_view);
+        let to_clip = self.transform(&mesh, &DefaultVertexShader, &uniforms, &camera.projection);
+        if emitter.blend == BlendMode::Opaque {
+            self.rasterize(&to_clip, &DefaultFragmentShader, &uniforms, BlendMode::Opaque, canvas);
+            return;
+        }
+        self.queue_transparent(to_clip, emitter.texture, &material);
+    }
+    // Draws one mesh many times without copying it, one model matrix per instance
+    // Tints multiply the material diffuse color, missing ones are white
+    pub fn draw_instanced(&mut self, surface: Surface, instances: &[Mat4], tints: &[Color], camera: &Camera, canvas: &mut Canvas) {
+        let mut tinted = surface.material.clone();
+        let view = View::new(camera);
+        for (i, mat_model) in instances.iter().enumerate() {
+            tinted.diffuse = surface.material.diffuse * tints.get(i).copied().unwrap_or(Color::WHITE);
+            self.draw_deformed(Surface { material: &tinted, ..surface }, *mat_model, Deform::default(), &view, canvas);
+        }
+    }
+    // Geometry pass of the deferred path, only fills the G-buffer and depth
+    // Blended models can't be deferred, they're queued for draw_transparent like in draw
+    // So is the faded level while changing LODs, the solid one goes to the G-buffer
+    // Call light_gbuffer once everything is drawn, then draw_transparent
+    // Reflections are only done by the forward path
+    pub fn draw_deferred(&mut self, model: &Model, camera: &Camera, gbuffer: &mut GBuffer, canvas: &mut Canvas) {
+        if model.material.blend != BlendMode::Opaque {
+            self.draw(model, camera, canvas);
+            return;
+        }
+        let pose = self.pose(model);
+        let view = View::new(camera);
+        let (solid, fading) = lod_levels(model);
+        if let Some((level, alpha)) = fading {
+            let deform = Deform { joints: &pose, morph_weights: &model.morph_weights };
+            let surface = Surface { mesh: model.lod_mesh(level), texture: model.texture, material: &faded(&model.material, alpha) };
+            self.draw_deformed(surface, model.get_model_mat(), deform, &view, canvas);
+        }
+        let morphed = self.morphed(model.lod_mesh(solid), &model.morph_weights);
+        let Some(mesh) = morphed.as_deref().or(self.assets.mesh(model.lod_mesh(solid))) else { return };
+        let visible = in_frustum(mesh, &pose, model.get_model_mat(), &view.frustum);
+        self.cull_stats.count(visible);
+        if !visible {
+            return;
+        }
+        let uniforms = DefaultUniforms {
+            shading: ShadingMode::Phong,    // Skips per vertex lighting
+            joints: &pose,
+            ..self.uniforms(model, camera)
+        };
+        let to_clip = self.transform(mesh, &DefaultVertexShader, &uniforms, &camera.projection);
+        let mut target = GBufferTarget { gbuffer, canvas };
+        self.rasterize(&to_clip, &GBufferShader, &uniforms, BlendMode::Opaque, &mut target);
+    }
+    // Lighting pass of the deferred path
+    pub fn light_gbuffer(&self, gbuffer: &mut GBuffer, camera: &Camera, canvas: &mut Canvas) {
+        gbuffer.light(canvas, &self.lights, &self.fog, camera.get_view_mat(), &camera.projection);
+    }
+    // Draws with custom shaders, blended draws aren't queued, only sorted within the mesh
+    // Target is usually the canvas, anything that can store the fragment shader output works
+    // Shaders are the vertex and fragment shader pair, the view matrix comes from the uniforms
+    pub fn draw_with<U, VS, FS, T>(&self, mesh: &Mesh, (vs, fs): (&VS, &FS), uniforms: &U, projection: &Projection, blend: BlendMode, target: &mut T)
+    where
+        VS: VertexShader<U>,
+        FS: FragmentShader<U, Varyings = VS::Varyings>,
+        T: RenderTarget<FS::Output>,
+    {
+        let mut to_clip = self.transform(mesh, vs, uniforms, projection);
+        if blend != BlendMode::Opaque {
+            to_clip.sort_by(|a, b| tri_depth(a).total_cmp(&tri_depth(b)));
+        }
+        self.rasterize(&to_clip, fs, uniforms, blend, target);
+    }
+    // Transparent pass, call after all opaque models have been drawn
+    pub fn draw_transparent(&mut self, camera: &Camera, canvas: &mut Canvas) {
+        self.end_morph_frame();
+        self.depth_sort();
+        let mat_view = camera.get_view_mat();
+        let transparent = std::mem::take(&mut self.transparent);
+        for t in transparent.iter() {
+            // Only the fragment part of the uniforms is used from here on
+            let uniforms = DefaultUniforms {
+                texture: self.texture(t.texture),
+                normal_map: self.texture(t.normal_map),
+                alpha_cutoff: t.alpha_cutoff,
+                reflectivity: t.reflectivity,
+                unlit: t.unlit,
+                ..self.default_uniforms_base(mat_view)
+            };
+            self.rasterize(std::slice::from_ref(&t.tri), &DefaultFragmentShader, &uniforms, t.blend, canvas);
+        }
+    }
+    // Fills every pixel nothing was drawn to with the environment
+    // Call after the opaque models and before draw_transparent
+    pub fn draw_skybox(&self, camera: &Camera, canvas: &mut Canvas) {
+        let Some(environment) = &self.environment else { return };
+        // Only the view rotation matters, the sky is infinitely far away
+        let inv_view = Mat3::from_mat4(camera.get_view_mat()).transpose();
+        let mat_proj = camera.projection.matrix();
+        let inv_proj = mat_proj.inverse();
+        // Between two points on the pixel's ray, they all point the same way with orthographic
+        let near = camera.projection.near();
+        let (near, far) = (mat_proj.project_point3(Vec3::new(0.0, 0.0, near)).z, mat_proj.project_point3(Vec3::new(0.0, 0.0, near + 1.0)).z);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if canvas.depth[x + y * WIDTH] != 0.0 {
+                    continue;
+                }
+                let ndc = Vec3::new((x as f32 + 0.5) / (0.5 * WIDTH as f32) - 1.0, (y as f32 + 0.5) / (0.5 * HEIGHT as f32) - 1.0, near);
+                let dir = inv_view * (inv_proj.project_point3(Vec3::new(ndc.x, ndc.y, far)) - inv_proj.project_point3(ndc));
+                canvas.pixels[x + y * WIDTH] = environment.sample(dir).to_u32();
+            }
+        }
+    }
+    // Draws a whole scene: shadows, every mesh node, the skybox and the transparent pass
+    // Lights on nodes are used on top of the renderer lights, placed by their node
+    // Nodes are drawn in the bind pose, morph targets use their own weights
+    pub fn render_scene(&mut self, scene: &mut Scene, camera: &Camera, canvas: &mut Canvas) {
+        scene.update();
+        let own_lights = self.lights.len();
+        self.lights.extend(scene.take_lights());
 
-        for tri in model.mesh.triangles.iter() {
-            // Model transform
-            let mut p1 = mat_model * tri.v[0].pos.extend(1.0);
-            let mut p2 = mat_model * tri.v[1].pos.extend(1.0);
-            let mut p3 = mat_model * tri.v[2].pos.extend(1.0);
-            let mod_tran_inv = Mat3::from_mat4(mat_model).inverse().transpose();    // For normals
-            let n1 = (mod_tran_inv * tri.v[0].normal).normalize();
-            let n2 = (mod_tran_inv * tri.v[1].normal).normalize();
-            let n3 = (mod_tran_inv * tri.v[2].normal).normalize();
-
-            // Ambient light
-            let ambient_strength = 0.05;
-
-            // Diffuse light
-            let dir_light = Vec3::new(0.0, 0.0, -1.0).normalize();
-            let lit1 = Vec3::dot(n1, (dir_light - p1.xyz()).normalize()).clamp(0.0, 1.0);
-            let lit2 = Vec3::dot(n2, (dir_light - p2.xyz()).normalize()).clamp(0.0, 1.0);
-            let lit3 = Vec3::dot(n3, (dir_light - p3.xyz()).normalize()).clamp(0.0, 1.0);
-
-            // Specular light
-
-            // View transform
-            let mat_view = camera.get_view_mat();
-            p1 = mat_view * p1;
-            p2 = mat_view * p2;
-            p3 = mat_view * p3;
+        let meshes = scene.meshes();
+        let casters: Vec<_> = meshes.iter().map(|n| (n.mesh.unwrap(), n.world)).collect();
+        self.render_shadow_casters(&casters);
+        let view = View::new(camera);
+        for node in meshes {
+            let surface = Surface { mesh: node.mesh.unwrap(), texture: node.texture, material: &node.material };
+            self.draw_deformed(surface, node.world, Deform::default(), &view, canvas);
+        }
+        self.draw_skybox(camera, canvas);
+        self.draw_transparent(camera, canvas);
+
+        scene.return_lights(self.lights.drain(own_lights..));
+    }
+    // Closest model under a canvas pixel, skinned models are hit in their bind pose
+    pub fn pick(&self, models: &[&Model], camera: &Camera, x: f32, y: f32) -> Option<Pick> {
+        let ray = camera.screen_ray(x, y);
+        let mut best: Option<Pick> = None;
+        for (i, model) in models.iter().enumerate() {
+            let Some(mesh) = self.assets.mesh(model.mesh) else { continue };
+            // Same t in model space as long as the direction isn't normalized again
+            let local = ray.transform(model.get_model_mat().inverse());
+            let Some(hit) = mesh.bvh.intersect(&mesh.triangles, &local) else { continue };
+            if best.is_none_or(|b| hit.t < b.hit.t) {
+                best = Some(Pick { model: i, world: ray.at(hit.t), hit });
+            }
+        }
+        return best;
+    }
+    // Canvas position of a world space point, None if it's behind the near plane
+    pub fn world_to_canvas(&self, camera: &Camera, world: Vec3) -> Option<Vec3> {
+        let view = camera.get_view_mat().transform_point3(world);
+        if view.z < camera.projection.near() {
+            return None;
+        }
+        let mut pos = camera.projection.matrix().project_point3(view);
+        Canvas::viewport_to_canvas(&mut pos);
+        return Some(pos);
+    }
+    // Default shader uniforms for a model, for draw_with with the default shaders
+    pub fn uniforms(&self, model: &Model, camera: &Camera) -> DefaultUniforms<'_> {
+        return self.default_uniforms(model.get_model_mat(), model.texture, &model.material, camera.get_view_mat());
+    }
+    fn default_uniforms(&self, mat_model: Mat4, texture: Option<TextureHandle>, material: &Material, mat_view: Mat4) -> DefaultUniforms<'_> {
+        return DefaultUniforms {
+            mat_model,
+            mat_normal: Mat3::from_mat4(mat_model).inverse().transpose(),
+            diffuse: material.diffuse,
+            texture: self.texture(texture),
+            normal_map: self.texture(material.normal_map),
+            alpha_cutoff: material.alpha_cutoff,
+            reflectivity: material.reflectivity,
+            unlit: material.unlit,
+            material_id: material.id,
+            ..self.default_uniforms_base(mat_view)
+        };
+    }
+    fn default_uniforms_base(&self, mat_view: Mat4) -> DefaultUniforms<'_> {
+        return DefaultUniforms {
+            mat_model: Mat4::IDENTITY,
+            mat_view,
+            mat_normal: Mat3::IDENTITY,
+            joints: &[],
+            diffuse: Color::WHITE,
+            texture: None,
+            normal_map: None,
+            alpha_cutoff: None,
+            shading: self.shading,
+            lights: &self.lights,
+            fog: &self.fog,
+            environment: self.environment.as_ref(),
+            reflectivity: 0.0,
+            camera_pos: mat_view.inverse().transform_point3(Vec3::ZERO),
+            unlit: false,
+            material_id: 0,
+        };
+    }
+    // Unloaded textures draw as untextured
+    fn texture(&self, handle: Option<TextureHandle>) -> Option<&Texture> {
+        return self.assets.texture(handle?);
+    }
+    // Vertex shader, culling, near clipping and projection
+    fn transform<U, VS: VertexShader<U>>(&self, mesh: &Mesh, vs: &VS, uniforms: &U, projection: &Projection) -> Vec<Triangle<RasterVertex<VS::Varyings>>> {
+        let mat_proj = projection.matrix();
+        //let mut to_clip = Vec::<Triangle>::with_capacity(self.to_render.len());
+        let mut to_clip = Vec::new();
+
+        for tri in mesh.triangles.iter() {
+            // Model and view transform
+            let (p1, var1) = vs.vertex(&tri.v[0], uniforms);
+            let (p2, var2) = vs.vertex(&tri.v[1], uniforms);
+            let (p3, var3) = vs.vertex(&tri.v[2], uniforms);
 
             // Calculate plane normal for clipping
             let line1 = p2 - p1;
             let line2 = p3 - p1;
-            let normal = Vec3::cross(line1.xyz(), line2.xyz()).normalize();
+            let normal = Vec3::cross(line1, line2).normalize();
 
             // Skip if side is invisible (Culling)
-            if Vec3::dot(normal, p1.xyz()) >= 0.0 {
+            if Vec3::dot(normal, projection.eye_to(p1)) >= 0.0 {
                 continue;
             }
 
             // Create tri to clip and project
             let tri_to_clip = Triangle::new(
-                Vertex::new(p1.xyz(), n1.xyz(), tri.v[0].texture, scale_color(tri.v[0].color, ambient_strength + lit1), lit1),
-                Vertex::new(p2.xyz(), n2.xyz(), tri.v[1].texture, scale_color(tri.v[1].color, ambient_strength + lit2), lit2),
-                Vertex::new(p3.xyz(), n3.xyz(), tri.v[2].texture, scale_color(tri.v[2].color, ambient_strength + lit3), lit3),
+                RasterVertex { pos: p1, var: var1 },
+                RasterVertex { pos: p2, var: var2 },
+                RasterVertex { pos: p3, var: var3 },
             );
 
             // Clip triangle
-            let mut clipped = clip_triangle(&tri_to_clip, &Vec3::new(0.0, 0.0, 0.1), &Vec3::new(0.0, 0.0, 1.0));
-            for mut tri_c in clipped.iter_mut() {
+            let mut clipped = clip_triangle(&tri_to_clip, &Vec3::new(0.0, 0.0, projection.near()), &Vec3::new(0.0, 0.0, 1.0));
+            for tri_c in clipped.iter_mut() {
                 // Project it
-                tri_c.v[0].pos = self.mat_proj.project_point3(tri_c.v[0].pos);
-                tri_c.v[1].pos = self.mat_proj.project_point3(tri_c.v[1].pos);
-                tri_c.v[2].pos = self.mat_proj.project_point3(tri_c.v[2].pos);
+                tri_c.v[0].pos = mat_proj.project_point3(tri_c.v[0].pos);
+                tri_c.v[1].pos = mat_proj.project_point3(tri_c.v[1].pos);
+                tri_c.v[2].pos = mat_proj.project_point3(tri_c.v[2].pos);
 
                 // Scale into view
                 for vertex in tri_c.v.iter_mut() {
@@ -83,10 +469,18 @@ pub fn draw(&mut self, model: &Model, camera: &Camera, canvas: &mut Canvas) {
                 to_clip.push(tri_c.clone());
             }
         }
-
-        for tri in &to_clip {
+        return to_clip;
+    }
+    // Screenspace clipping and drawing
+    fn rasterize<U, V, FS, T>(&self, to_clip: &[Triangle<RasterVertex<V>>], fs: &FS, uniforms: &U, blend: BlendMode, target: &mut T)
+    where
+        V: Varyings,
+        FS: FragmentShader<U, Varyings = V>,
+        T: RenderTarget<FS::Output>,
+    {
+        for tri in to_clip {
             // Screenspace clip
-            let mut queue: VecDeque<Triangle> = VecDeque::new();
+            let mut queue: VecDeque<Triangle<RasterVertex<V>>> = VecDeque::new();
             queue.push_back(tri.clone());
             let mut new_triangles = 1;
 
@@ -118,47 +512,96 @@ pub fn draw(&mut self, model: &Model, camera: &Camera, canvas: &mut Canvas) {
             for tri in queue.iter() {
                 if self.wireframe {
                     draw_triangle(
-                        canvas,
-                        tri.v[0].pos.xy().as_ivec2(),
-                        tri.v[1].pos.xy().as_ivec2(),
-                        tri.v[2].pos.xy().as_ivec2(),
-                        0xFF00FF00, 0xFF00FF00, 0xFF00FF00, false);
-                }
-                if model.texture_index != -1 {
-                    let texture = self.textures.get(&model.texture_index).unwrap();
-                    draw_triangle_tex(canvas, 
-                        tri,
-                        &texture.0,
-                        (texture.1, texture.2)
-                    );
-                } else {
-                    draw_triangle(canvas,
+                        target.canvas(),
                         tri.v[0].pos.xy().as_ivec2(),
                         tri.v[1].pos.xy().as_ivec2(),
                         tri.v[2].pos.xy().as_ivec2(),
-                        tri.v[0].color,
-                        tri.v[1].color,
-                        tri.v[2].color,
-                        true);
+                        Color::rgb(0.0, 1.0, 0.0), Color::rgb(0.0, 1.0, 0.0), Color::rgb(0.0, 1.0, 0.0), false);
                 }
+                draw_triangle_tex(target, tri, fs, uniforms, blend);
             }
         }
     }
-    pub fn load_texture(&mut self, path: &str) -> i32 {
-        let (pixels, width, height) = load_pixels(path);
-        self.textures.insert(self.tex_num, (pixels, width, height));
-        let cur = self.tex_num;
-        self.tex_num += 1;
-        return cur; 
-    }
-    //pub fn depth_sort(&mut self) {
-    //    // Painters algorithm, depth sorting
-    //    self.to_render.sort_by(|a, b| {
-    //        let z1 = (a.v[0].pos.z + a.v[1].pos.z + a.v[2].pos.z) / 3.0;
-    //        let z2 = (b.v[0].pos.z + b.v[1].pos.z + b.v[2].pos.z) / 3.0;
-    //        z1.total_cmp(&z2)
-    //    });
-    //}
+    // Shadow pass, call once per frame before drawing with every model that should cast shadows
+    // Skinned and morphed models cast the shadow of their current shape
+    pub fn render_shadows(&mut self, models: &[&Model]) {
+        let mut posed = Vec::with_capacity(models.len());
+        for m in models.iter() {
+            let morphed = self.morphed(m.lod_mesh(m.lod), &m.morph_weights);
+            let pose = self.pose(m);
+            let mesh = morphed.as_deref().or(self.assets.mesh(m.lod_mesh(m.lod)));
+            posed.push(match mesh {
+                Some(mesh) if !pose.is_empty() => Some(Rc::new(skin_mesh(mesh, &pose))),
+                _ => morphed,
+            });
+        }
+        let casters: Vec<_> = models.iter().zip(posed.iter())
+            .filter_map(|(m, posed)| Some((posed.as_deref().or(self.assets.mesh(m.lod_mesh(m.lod)))?, m.get_model_mat())))
+            .collect();
+        for light in self.lights.iter_mut() {
+            light.update_shadow(&casters);
+        }
+    }
+    // Blends a mesh's morph targets once a frame, None if it has none or every weight is 0
+    // Later calls with the same weights share the result, the blended buffer is reused
+    fn morphed(&mut self, handle: MeshHandle, weights: &[f32]) -> Option<Rc<Mesh>> {
+        let mesh = self.assets.mesh(handle)?;
+        if mesh.morph_targets.is_empty() {
+            return None;
+        }
+        let i = match self.morph_cache.iter().position(|c| c.mesh == handle) {
+            Some(i) => i,
+            None => {
+                self.morph_cache.push(MorphCache { mesh: handle, weights: Vec::new(), fresh: false, morphed: None });
+                self.morph_cache.len() - 1
+            }
+        };
+        let cache = &mut self.morph_cache[i];
+        if !cache.fresh || cache.weights != weights {
+            let mut out = cache.morphed.take().and_then(|m| Rc::try_unwrap(m).ok()).unwrap_or_else(Mesh::new);
+            cache.morphed = mesh.morph_into(weights, &mut out).then(|| Rc::new(out));
+            cache.weights.clear();
+            cache.weights.extend_from_slice(weights);
+            cache.fresh = true;
+        }
+        return cache.morphed.clone();
+    }
+    // Meshes not morphed this frame are dropped, the rest are blended again on their next use
+    fn end_morph_frame(&mut self) {
+        self.morph_cache.retain(|c| c.fresh);
+        self.morph_cache.iter_mut().for_each(|c| c.fresh = false);
+    }
+    fn render_shadow_casters(&mut self, casters: &[(MeshHandle, Mat4)]) {
+        let casters: Vec<_> = casters.iter()
+            .filter_map(|(mesh, mat)| Some((self.assets.mesh(*mesh)?, *mat)))
+            .collect();
+        for light in self.lights.iter_mut() {
+            light.update_shadow(&casters);
+        }
+    }
+    // Off-screen target with its color buffers registered as textures, blank until resolved
+    // Attachment textures are named "name.1", "name.2" and so on
+    pub fn create_render_texture(&mut self, name: &str, attachments: usize, downscale: usize) -> RenderTexture {
+        let textures = (0..=attachments).map(|i| {
+            let name = if i == 0 { name.to_string() } else { format!("{}.{}", name, i) };
+            self.assets.add_texture(&name, (vec![0u32], 1, 1))
+        }).collect();
+        return RenderTexture::new(attachments, textures, downscale);
+    }
+    // Copies the render texture into its textures, call once drawing into it is done
+    pub fn resolve(&mut self, rt: &RenderTexture) {
+        let buffers = std::iter::once(&rt.canvas.pixels[..]).chain(rt.attachments.iter().map(|a| &a[..]));
+        for (pixels, handle) in buffers.zip(rt.textures.iter()) {
+            self.assets.set_texture(*handle, downsample(pixels, rt.downscale));
+        }
+    }
+    fn depth_sort(&mut self) {
+        // Painters algorithm, depth sorting (back to front)
+        match self.sort_mode {
+            SortMode::PerModel => self.transparent.sort_by(|a, b| a.model_depth.total_cmp(&b.model_depth)),
+            SortMode::PerTriangle => self.transparent.sort_by(|a, b| tri_depth(&a.tri).total_cmp(&tri_depth(&b.tri))),
+        }
+    }
     //pub fn draw(&mut self, canvas: &mut Canvas, texture: Option<&Vec<u32>>) {
     //    canvas.clear(0xFF020202);
     //    if texture.is_some() {
@@ -187,3 +630,29 @@ pub fn load_texture(&mut self, path: &str) -> i32 {
     //    self.to_render.clear();
     //}
 }
+
+// Canvas depth, bigger is closer
+fn tri_depth<V>(tri: &Triangle<RasterVertex<V>>) -> f32 {
+    return (tri.v[0].pos.z + tri.v[1].pos.z + tri.v[2].pos.z) / 3.0;
+}
+
+// Level drawn solid and the one blended over it with its alpha, while a model changes LODs
+// The new level fades in over the old one during the first half, then the old one fades out
+fn lod_levels(model: &Model) -> (usize, Option<(usize, f32)>) {
+    return match model.lod_fade {
+        None => (model.lod, None),
+        Some((from, t)) if t < 0.5 => (from, Some((model.lod, t * 2.0))),
+        Some((from, t)) => (model.lod, Some((from, (1.0 - t) * 2.0))),
+    };
+}
+fn faded(material: &Material, alpha: f32) -> Material {
+    let mut faded = material.clone();
+    faded.blend = BlendMode::Alpha;
+    faded.diffuse.a *= alpha;
+    return faded;
+}
+
+// Bounding sphere test, grown to fit every pose for skinned meshes
+fn in_frustum(mesh: &Mesh, joints: &[Mat4], mat_model: Mat4, frustum: &Frustum) -> bool {
+    return frustum.intersects_sphere(&mesh.sphere.skinned(joints).transform(mat_model));
+}
@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+use glam::{Vec3, Quat, Mat4};
+
+use crate::animation::Animation;
+use crate::assets::{MeshHandle, TextureHandle};
+use crate::camera::Camera;
+use crate::lighting::{Light, LightKind};
+use crate::material::Material;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+pub struct Node {
+    pub name: String,
+    pub translation: Vec3,  // Relative to the parent
+    pub scale: Vec3,
+    pub rotation: Quat,
+    pub mesh: Option<MeshHandle>,
+    pub texture: Option<TextureHandle>,
+    pub material: Material,
+    pub light: Option<Light>,   // Position and direction are relative to the node
+    pub camera: Option<Camera>, // Looks down the node's +z, see Scene::camera
+    pub animation: Option<Animation>,   // Drives the local transform, see Scene::animate
+    pub world: Mat4,    // Set by Scene::update
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+impl Node {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            translation: Vec3::ZERO,
+            scale: Vec3::splat(1.0),
+            rotation: Quat::IDENTITY,
+            mesh: None,
+            texture: None,
+            material: Material::default(),
+            light: None,
+            camera: None,
+            animation: None,
+            world: Mat4::IDENTITY,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+    pub fn with_mesh(mut self, mesh: MeshHandle, texture: Option<TextureHandle>) -> Self {
+        self.mesh = Some(mesh);
+        self.texture = texture;
+        return self;
+    }
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        return self;
+    }
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.camera = Some(camera);
+        return self;
+    }
+    pub fn with_animation(mut self, animation: Animation) -> Self {
+        self.animation = Some(animation);
+        return self;
+    }
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        return self;
+    }
+    pub fn get_local_mat(&self) -> Mat4 {
+        return Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation);
+    }
+}
+
+// Node hierarchy, parents are always added before their children
+// so world matrices can be propagated in a single pass
+pub struct Scene {
+    nodes: Vec<Node>,
+    pub active_camera: Option<NodeId>,
+    taken: Vec<(NodeId, Vec3, LightKind)>,  // Local placement of lights lent to the renderer
+}
+impl Scene {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new(), active_camera: None, taken: Vec::new() }
+    }
+    pub fn add(&mut self, parent: Option<NodeId>, mut node: Node) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        node.parent = parent;
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(id);
+        }
+        self.nodes.push(node);
+        return id;
+    }
+    pub fn node(&self, id: NodeId) -> &Node {
+        return &self.nodes[id.0];
+    }
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        return &mut self.nodes[id.0];
+    }
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        return self.nodes.iter().position(|n| n.name == name).map(NodeId);
+    }
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        return self.nodes[id.0].parent;
+    }
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        return &self.nodes[id.0].children;
+    }
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        return self.nodes.iter().enumerate().map(|(i, n)| (NodeId(i), n));
+    }
+    // Advances every node animation by dt seconds and applies it to the local transforms
+    pub fn animate(&mut self, dt: f32) {
+        for node in self.nodes.iter_mut() {
+            let Some(animation) = node.animation.as_mut() else { continue };
+            animation.update(dt);
+            animation.apply(&mut node.translation, &mut node.rotation, &mut node.scale);
+        }
+    }
+    // Propagates the local transforms into every node's world matrix
+    pub fn update(&mut self) {
+        for i in 0..self.nodes.len() {
+            let parent = self.nodes[i].parent.map_or(Mat4::IDENTITY, |p| self.nodes[p.0].world);
+            self.nodes[i].world = parent * self.nodes[i].get_local_mat();
+        }
+    }
+    // World matrix from the current local transforms, doesn't need update
+    pub fn world(&self, id: NodeId) -> Mat4 {
+        let node = &self.nodes[id.0];
+        let parent = node.parent.map_or(Mat4::IDENTITY, |p| self.world(p));
+        return parent * node.get_local_mat();
+    }
+    // Copy of the active camera, placed where its node is
+    pub fn camera(&self) -> Option<Camera> {
+        let id = self.active_camera?;
+        let mut camera = self.nodes[id.0].camera.clone()?;
+        camera.set_transform(self.world(id));
+        return Some(camera);
+    }
+    // Nodes with a mesh, in hierarchy order
+    pub fn meshes(&self) -> Vec<&Node> {
+        return self.nodes.iter().filter(|n| n.mesh.is_some()).collect();
+    }
+    // Moves every node light out of the scene, placed in world space, give them back with return_lights
+    pub fn take_lights(&mut self) -> Vec<Light> {
+        let mut lights = Vec::new();
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            let Some(mut light) = node.light.take() else { continue };
+            self.taken.push((NodeId(i), light.pos, light.kind));
+            light.pos = node.world.transform_point3(light.pos);
+            light.kind = match light.kind {
+                LightKind::Point => LightKind::Point,
+                LightKind::Directional(dir) => LightKind::Directional(node.world.transform_vector3(dir).normalize()),
+                LightKind::Spot { dir, cutoff } => LightKind::Spot { dir: node.world.transform_vector3(dir).normalize(), cutoff },
+            };
+            lights.push(light);
+        }
+        return lights;
+    }
+    // Lights in the same order take_lights returned them
+    pub fn return_lights(&mut self, lights: impl Iterator<Item = Light>) {
+        for ((id, pos, kind), mut light) in self.taken.drain(..).zip(lights) {
+            light.pos = pos;
+            light.kind = kind;
+            self.nodes[id.0].light = Some(light);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use glam::{Vec3, Quat, EulerRot};
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+
+use crate::animation::{Animation, Clip, Interpolation, Keyframe, LoopMode, Track};
+use crate::assets::{MeshHandle, TextureHandle};
+use crate::camera::{Camera, Projection, DEFAULT_FOV};
+use crate::color::{Color, BlendMode};
+use crate::cubemap::CubeMap;
+use crate::fog::{FogMode, HeightFog};
+use crate::json::Json;
+use crate::lighting::{Light, ShadingMode};
+use crate::material::Material;
+use crate::renderer::{Renderer, SortMode};
+use crate::scene::{Node, Scene};
+use crate::shadow::ShadowMap;
+
+// Everything a scene file describes that doesn't live in the renderer
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub background: Color,
+}
+
+// Loads a JSON scene file (see scenes/arctic.json), assets go into the renderer
+// and the render settings and lights are applied to it
+pub fn load_scene(path: &str, renderer: &mut Renderer) -> Result<SceneFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let json = Json::parse(&text).map_err(|e| format!("{}:{}", path, e))?;
+    return Loader::default().load(&json, renderer).map_err(|e| format!("{}: {}", path, e));
+}
+
+#[derive(Default)]
+struct Loader {
+    textures: HashMap<String, TextureHandle>,
+    meshes: HashMap<String, MeshHandle>,
+    materials: HashMap<String, Material>,
+    clips: HashMap<String, Rc<Clip>>,
+}
+impl Loader {
+    fn load(mut self, json: &Json, renderer: &mut Renderer) -> Result<SceneFile, String> {
+        keys(json, "scene", &["settings", "camera", "textures", "meshes", "materials", "clips", "lights", "nodes"])?;
+
+        // Assets first, everything else refers to them by name
+        for (name, path) in members(json.get("textures"), "textures")? {
+            let path = path.as_str().ok_or(format!("texture \"{}\": expected a file path", name))?;
+            let handle = renderer.assets.try_load_texture(path).map_err(|e| format!("texture \"{}\": {}", name, e))?;
+            self.textures.insert(name.clone(), handle);
+        }
+        for (name, path) in members(json.get("meshes"), "meshes")? {
+            let path = path.as_str().ok_or(format!("mesh \"{}\": expected a file path", name))?;
+            let handle = renderer.assets.try_load_mesh(path).map_err(|e| format!("mesh \"{}\": {}", name, e))?;
+            self.meshes.insert(name.clone(), handle);
+        }
+        for (name, material) in members(json.get("materials"), "materials")? {
+            let material = self.material(material).map_err(|e| format!("material \"{}\": {}", name, e))?;
+            self.materials.insert(name.clone(), material);
+        }
+        for (name, c) in members(json.get("clips"), "clips")? {
+            let c = clip(name, c).map_err(|e| format!("clip \"{}\": {}", name, e))?;
+            self.clips.insert(name.clone(), Rc::new(c));
+        }
+
+        let background = self.settings(json.get("settings"), renderer).map_err(|e| format!("settings: {}", e))?;
+        if let Some(lights) = json.get("lights") {
+            let lights = lights.as_array().ok_or("lights: expected an array")?;
+            renderer.lights = lights.iter().enumerate()
+                .map(|(i, l)| light(l).map_err(|e| format!("light {}: {}", i, e)))
+                .collect::<Result<_, _>>()?;
+        }
+
+        let mut scene = Scene::new();
+        let nodes = json.get("nodes").map_or(Some(&[][..]), Json::as_array).ok_or("nodes: expected an array")?;
+        for (i, node) in nodes.iter().enumerate() {
+            let name = node.get("name").and_then(Json::as_str).map_or(format!("node {}", i), |n| format!("node \"{}\"", n));
+            self.node(node, &mut scene).map_err(|e| format!("{}: {}", name, e))?;
+        }
+
+        let camera = camera(json.get("camera"), &mut scene).map_err(|e| format!("camera: {}", e))?;
+        return Ok(SceneFile { scene, camera, background });
+    }
+    fn settings(&self, json: Option<&Json>, renderer: &mut Renderer) -> Result<Color, String> {
+        let Some(json) = json else { return Ok(renderer.fog.color) };
+        keys(json, "settings", &["background", "shading", "wireframe", "sort", "fog", "skybox"])?;
+        let background = json.get("background").map_or(Ok(Color::from_u32(0xFF020202)), color)?;
+        renderer.fog.color = background;
+
+        if let Some(shading) = json.get("shading") {
+            renderer.shading = match shading.as_str() {
+                Some("gouraud") => ShadingMode::Gouraud,
+                Some("phong") => ShadingMode::Phong,
+                _ => return Err("shading: expected \"gouraud\" or \"phong\"".to_string()),
+            };
+        }
+        if let Some(wireframe) = json.get("wireframe") {
+            renderer.wireframe = wireframe.as_bool().ok_or("wireframe: expected true or false")?;
+        }
+        if let Some(sort) = json.get("sort") {
+            renderer.sort_mode = match sort.as_str() {
+                Some("model") => SortMode::PerModel,
+                Some("triangle") => SortMode::PerTriangle,
+                _ => return Err("sort: expected \"model\" or \"triangle\"".to_string()),
+            };
+        }
+        if let Some(fog) = json.get("fog") {
+            keys(fog, "fog", &["mode", "color", "density", "start", "end", "height"])?;
+            renderer.fog.mode = match fog.get("mode").and_then(Json::as_str) {
+                Some("off") => FogMode::Off,
+                Some("linear") => FogMode::Linear,
+                Some("exp") => FogMode::Exp,
+                Some("exp2") => FogMode::Exp2,
+                _ => return Err("fog mode: expected \"off\", \"linear\", \"exp\" or \"exp2\"".to_string()),
+            };
+            if let Some(c) = fog.get("color") {
+                renderer.fog.color = color(c).map_err(|e| format!("fog color: {}", e))?;
+            }
+            renderer.fog.density = optional_number(fog, "density", renderer.fog.density)?;
+            renderer.fog.start = optional_number(fog, "start", renderer.fog.start)?;
+            renderer.fog.end = optional_number(fog, "end", renderer.fog.end)?;
+            if let Some(height) = fog.get("height") {
+                keys(height, "fog height", &["base", "falloff", "density"])?;
+                renderer.fog.height = Some(HeightFog {
+                    base: optional_number(height, "base", 0.0)?,
+                    falloff: optional_number(height, "falloff", 1.0)?,
+                    density: optional_number(height, "density", 0.1)?,
+                });
+            }
+        }
+        if let Some(skybox) = json.get("skybox") {
+            let cubemap = match skybox {
+                Json::String(path) => CubeMap::try_from_equirectangular(path, 512),
+                Json::Array(faces) if faces.len() == 6 && faces.iter().all(|f| f.as_str().is_some()) => {
+                    let faces: Vec<&str> = faces.iter().filter_map(Json::as_str).collect();
+                    CubeMap::try_from_faces([faces[0], faces[1], faces[2], faces[3], faces[4], faces[5]])
+                }
+                _ => return Err("skybox: expected a panorama path or 6 face paths".to_string()),
+            };
+            renderer.environment = Some(cubemap.map_err(|e| format!("skybox: {}", e))?);
+        }
+        return Ok(background);
+    }
+    fn material(&self, json: &Json) -> Result<Material, String> {
+        keys(json, "material", &["diffuse", "normal_map", "blend", "alpha_cutoff", "reflectivity", "id"])?;
+        let mut material = Material::default();
+        if let Some(diffuse) = json.get("diffuse") {
+            material.diffuse = color(diffuse).map_err(|e| format!("diffuse: {}", e))?;
+        }
+        if let Some(normal_map) = json.get("normal_map") {
+            material.normal_map = Some(self.texture(normal_map)?);
+        }
+        if let Some(blend) = json.get("blend") {
+            material.blend = match blend.as_str() {
+                Some("opaque") => BlendMode::Opaque,
+                Some("alpha") => BlendMode::Alpha,
+                Some("additive") => BlendMode::Additive,
+                Some("multiply") => BlendMode::Multiply,
+                Some("premultiplied") => BlendMode::Premultiplied,
+                _ => return Err("blend: expected \"opaque\", \"alpha\", \"additive\", \"multiply\" or \"premultiplied\"".to_string()),
+            };
+        }
+        if json.get("alpha_cutoff").is_some() {
+            material.alpha_cutoff = Some(optional_number(json, "alpha_cutoff", 0.5)?);
+        }
+        material.reflectivity = optional_number(json, "reflectivity", 0.0)?;
+        let id = optional_number(json, "id", 1.0)?;
+        if id < 1.0 || id > u16::MAX as f32 || id.fract() != 0.0 {
+            return Err("id: expected a whole number from 1 to 65535".to_string());
+        }
+        material.id = id as u16;
+        return Ok(material);
+    }
+    fn node(&self, json: &Json, scene: &mut Scene) -> Result<(), String> {
+        keys(json, "node", &["name", "parent", "mesh", "texture", "material", "translation", "rotation", "scale", "light", "camera", "animation"])?;
+        let name = json.get("name").map_or(Some(""), Json::as_str).ok_or("name: expected a string")?;
+        if !name.is_empty() && scene.find(name).is_some() {
+            return Err("there is already a node with this name".to_string());
+        }
+        let parent = match json.get("parent") {
+            Some(parent) => {
+                let parent = parent.as_str().ok_or("parent: expected a node name")?;
+                Some(scene.find(parent).ok_or(format!("unknown parent \"{}\", parents have to come before their children", parent))?)
+            }
+            None => None,
+        };
+
+        let mut node = Node::new(name);
+        if let Some(mesh) = json.get("mesh") {
+            node.mesh = Some(*lookup(&self.meshes, mesh, "mesh")?);
+        }
+        if let Some(texture) = json.get("texture") {
+            node.texture = Some(self.texture(texture)?);
+        }
+        if let Some(material) = json.get("material") {
+            node.material = lookup(&self.materials, material, "material")?.clone();
+        }
+        if let Some(translation) = json.get("translation") {
+            node.translation = vec3(translation).map_err(|e| format!("translation: {}", e))?;
+        }
+        if let Some(rotation) = json.get("rotation") {
+            let r = vec3(rotation).map_err(|e| format!("rotation: {}", e))?;
+            node.rotation = Quat::from_euler(EulerRot::XYZ, r.x.to_radians(), r.y.to_radians(), r.z.to_radians());
+        }
+        if let Some(scale) = json.get("scale") {
+            node.scale = match scale.as_f32() {
+                Some(s) => Vec3::splat(s),
+                None => vec3(scale).map_err(|_| "scale: expected a number or [x, y, z]".to_string())?,
+            };
+        }
+        if let Some(l) = json.get("light") {
+            node.light = Some(light(l).map_err(|e| format!("light: {}", e))?);
+        }
+        if let Some(c) = json.get("camera") {
+            if c.as_bool().ok_or("camera: expected true or false")? {
+                node.camera = Some(Camera::new(Vec3::ZERO, 0.10, 0.15));
+            }
+        }
+        if let Some(animation) = json.get("animation") {
+            node.animation = Some(self.animation(animation).map_err(|e| format!("animation: {}", e))?);
+        }
+        scene.add(parent, node);
+        return Ok(());
+    }
+    // {"clip": name, "loop": "once" / "loop" / "pingpong", "speed": 1}
+    fn animation(&self, json: &Json) -> Result<Animation, String> {
+        keys(json, "animation", &["clip", "loop", "speed"])?;
+        let c = json.get("clip").ok_or("clip is missing")?;
+        let mode = match json.get("loop").map_or(Some("loop"), Json::as_str) {
+            Some("once") => LoopMode::Once,
+            Some("loop") => LoopMode::Loop,
+            Some("pingpong") => LoopMode::PingPong,
+            _ => return Err("loop: expected \"once\", \"loop\" or \"pingpong\"".to_string()),
+        };
+        let mut animation = Animation::new(lookup(&self.clips, c, "clip")?.clone(), mode);
+        animation.speed = optional_number(json, "speed", 1.0)?;
+        return Ok(animation);
+    }
+    fn texture(&self, json: &Json) -> Result<TextureHandle, String> {
+        return lookup(&self.textures, json, "texture").copied();
+    }
+}
+
+// Keys are [time, x, y, z], rotations in degrees like node rotations, scale can also be [time, s]
+fn clip(name: &str, json: &Json) -> Result<Clip, String> {
+    keys(json, "clip", &["interpolation", "translation", "rotation", "scale"])?;
+    let interpolation = match json.get("interpolation").map_or(Some("linear"), Json::as_str) {
+        Some("step") => Interpolation::Step,
+        Some("linear") => Interpolation::Linear,
+        Some("cubic") => Interpolation::Cubic,
+        _ => return Err("interpolation: expected \"step\", \"linear\" or \"cubic\"".to_string()),
+    };
+    let track = |key: &str, value: &dyn Fn(&[f32]) -> Option<Vec3>| -> Result<Vec<Keyframe<Vec3>>, String> {
+        let Some(keys) = json.get(key) else { return Ok(Vec::new()) };
+        let keys = keys.as_array().ok_or(format!("{}: expected an array of keys", key))?;
+        let mut frames = Vec::<Keyframe<Vec3>>::new();
+        for (i, k) in keys.iter().enumerate() {
+            let error = || format!("{} key {}: expected [time, x, y, z]", key, i);
+            let k = k.as_floats().ok_or_else(error)?;
+            let (time, v) = k.split_first().ok_or_else(error)?;
+            if frames.last().is_some_and(|f| f.time > *time) {
+                return Err(format!("{} key {}: keys have to be in time order", key, i));
+            }
+            frames.push(Keyframe::new(*time, value(v).ok_or_else(error)?));
+        }
+        return Ok(frames);
+    };
+    let xyz = |v: &[f32]| match v {
+        [x, y, z] => Some(Vec3::new(*x, *y, *z)),
+        _ => None,
+    };
+    let translation = track("translation", &xyz)?;
+    let rotation = track("rotation", &xyz)?.into_iter()
+        .map(|k| Keyframe::new(k.time, Quat::from_euler(EulerRot::XYZ, k.value.x.to_radians(), k.value.y.to_radians(), k.value.z.to_radians())))
+        .collect();
+    let scale = track("scale", &|v| if let [s] = v { Some(Vec3::splat(*s)) } else { xyz(v) })?;
+    return Ok(Clip::new(name).with_tracks(
+        Track::new(translation, interpolation),
+        Track::new(rotation, interpolation),
+        Track::new(scale, interpolation),
+    ));
+}
+
+// Free camera, or "node" to look through a node with "camera": true
+// The projection is used by both, "orthographic": [width, height] or perspective with "fov" in degrees,
+// "far": "infinite" drops the far plane
+fn camera(json: Option<&Json>, scene: &mut Scene) -> Result<Camera, String> {
+    let Some(json) = json else { return Ok(Camera::new(Vec3::new(0.0, 0.0, -2.5), 0.10, 0.15)) };
+    keys(json, "camera", &["position", "yaw", "pitch", "speed", "sensitivity", "node", "fov", "near", "far", "orthographic"])?;
+    let near = optional_number(json, "near", 0.1)?;
+    let infinite = json.get("far").and_then(Json::as_str) == Some("infinite");
+    let projection = match json.get("orthographic") {
+        Some(size) => match size.as_floats().as_deref() {
+            Some([width, height]) => Projection::orthographic(*width, *height, near, optional_number(json, "far", 1000.0)?),
+            _ => return Err("orthographic: expected [width, height]".to_string()),
+        },
+        None if infinite => Projection::infinite(optional_number(json, "fov", DEFAULT_FOV)?, near),
+        None => Projection::perspective(optional_number(json, "fov", DEFAULT_FOV)?, near, optional_number(json, "far", 1000.0)?),
+    };
+
+    if let Some(node) = json.get("node") {
+        let name = node.as_str().ok_or("node: expected a node name")?;
+        let id = scene.find(name).ok_or(format!("unknown node \"{}\"", name))?;
+        let Some(camera) = scene.node_mut(id).camera.as_mut() else { return Err(format!("node \"{}\" has no camera", name)) };
+        camera.projection = projection;
+        scene.active_camera = Some(id);
+    }
+    let position = json.get("position").map_or(Ok(Vec3::new(0.0, 0.0, -2.5)), vec3).map_err(|e| format!("position: {}", e))?;
+    let mut camera = Camera::new(position, optional_number(json, "speed", 0.10)?, optional_number(json, "sensitivity", 0.15)?);
+    camera.set_rotation(optional_number(json, "yaw", 270.0)?, optional_number(json, "pitch", 0.0)?);
+    camera.projection = projection;
+    return Ok(camera);
+}
+
+fn light(json: &Json) -> Result<Light, String> {
+    keys(json, "light", &["type", "position", "direction", "cutoff", "color", "range", "shadow"])?;
+    let light_color = json.get("color").map_or(Ok(Color::WHITE), color).map_err(|e| format!("color: {}", e))?;
+    let position = json.get("position").map_or(Ok(Vec3::ZERO), vec3).map_err(|e| format!("position: {}", e))?;
+    let direction = || json.get("direction").ok_or("direction is missing".to_string())
+        .and_then(|d| vec3(d).map_err(|e| format!("direction: {}", e)));
+    let mut light = match json.get("type").map_or(Some("point"), Json::as_str) {
+        Some("point") => Light::new(position, light_color),
+        Some("directional") => Light::directional(direction()?, light_color),
+        Some("spot") => Light::spot(position, direction()?, optional_number(json, "cutoff", 30.0)?, light_color),
+        _ => return Err("type: expected \"point\", \"directional\" or \"spot\"".to_string()),
+    };
+    if json.get("range").is_some() {
+        light = light.with_range(optional_number(json, "range", 1.0)?);
+    }
+    if json.get("shadow").map_or(Some(false), Json::as_bool).ok_or("shadow: expected true or false")? {
+        light = light.with_shadow(ShadowMap::new());
+    }
+    return Ok(light);
+}
+
+// Named reference into one of the asset tables, lists the valid names when it's wrong
+fn lookup<'a, T>(table: &'a HashMap<String, T>, json: &Json, what: &str) -> Result<&'a T, String> {
+    let name = json.as_str().ok_or(format!("{}: expected a {} name", what, what))?;
+    return table.get(name).ok_or_else(|| {
+        let mut names: Vec<&str> = table.keys().map(String::as_str).collect();
+        names.sort();
+        if names.is_empty() {
+            format!("unknown {} \"{}\", none are defined", what, name)
+        } else {
+            format!("unknown {} \"{}\", defined are: {}", what, name, names.join(", "))
+        }
+    });
+}
+
+// Typos in key names would otherwise be silently ignored
+fn keys(json: &Json, what: &str, allowed: &[&str]) -> Result<(), String> {
+    let members = json.as_object().ok_or(format!("{}: expected an object", what))?;
+    for (key, _) in members {
+        if !allowed.contains(&key.as_str()) {
+            return Err(format!("unknown key \"{}\" in {}, expected one of: {}", key, what, allowed.join(", ")));
+        }
+    }
+    return Ok(());
+}
+
+fn members<'a>(json: Option<&'a Json>, what: &str) -> Result<&'a [(String, Json)], String> {
+    let Some(json) = json else { return Ok(&[]) };
+    return json.as_object().ok_or(format!("{}: expected an object of name: path", what));
+}
+
+fn optional_number(json: &Json, key: &str, default: f32) -> Result<f32, String> {
+    let Some(value) = json.get(key) else { return Ok(default) };
+    return value.as_f32().ok_or(format!("{}: expected a number", key));
+}
+
+fn vec3(json: &Json) -> Result<Vec3, String> {
+    match json.as_floats().as_deref() {
+        Some([x, y, z]) => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err("expected [x, y, z]".to_string()),
+    }
+}
+
+// [r, g, b] or [r, g, b, a], 0..1
+fn color(json: &Json) -> Result<Color, String> {
+    match json.as_floats().as_deref() {
+        Some([r, g, b]) => Ok(Color::rgb(*r, *g, *b)),
+        Some([r, g, b, a]) => Ok(Color::new(*r, *g, *b, *a)),
+        _ => Err("expected [r, g, b] or [r, g, b, a]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str) -> Result<SceneFile, String> {
+        return Loader::default().load(&Json::parse(text)?, &mut Renderer::new());
+    }
+    fn error(text: &str) -> String {
+        return load(text).err().expect("loaded");
+    }
+
+    #[test]
+    fn loads_the_example() {
+        // The shadow map canvas is built on the stack in debug builds, too big for a test thread
+        std::thread::Builder::new().stack_size(64 << 20).spawn(|| {
+            let mut renderer = Renderer::new();
+            let file = load_scene("scenes/arctic.json", &mut renderer).unwrap();
+            assert!(file.scene.find("ghost").is_some());
+            assert_eq!(renderer.lights.len(), 2);
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn missing_references_name_what_is_defined() {
+        let materials = r#""materials": { "red": { "diffuse": [1, 0, 0] }, "blue": { "diffuse": [0, 0, 1] } }"#;
+        assert_eq!(
+            error(&format!(r#"{{ {}, "nodes": [{{ "name": "a", "material": "green" }}] }}"#, materials)),
+            r#"node "a": unknown material "green", defined are: blue, red"#,
+        );
+        assert_eq!(error(r#"{ "nodes": [{ "mesh": "cube" }] }"#), r#"node 0: unknown mesh "cube", none are defined"#);
+        assert_eq!(error(r#"{ "nodes": [{ "texture": "crate" }] }"#), r#"node 0: unknown texture "crate", none are defined"#);
+        assert_eq!(error(r#"{ "nodes": [{ "animation": { "clip": "spin" } }] }"#), r#"node 0: animation: unknown clip "spin", none are defined"#);
+    }
+
+    #[test]
+    fn parents_come_first() {
+        let orphan = error(r#"{ "nodes": [{ "name": "child", "parent": "root" }, { "name": "root" }] }"#);
+        assert!(orphan.contains("unknown parent \"root\""), "{}", orphan);
+        assert!(load(r#"{ "nodes": [{ "name": "root" }, { "name": "child", "parent": "root" }] }"#).is_ok());
+        assert!(error(r#"{ "nodes": [{ "name": "a" }, { "name": "a" }] }"#).contains("already a node with this name"));
+    }
+
+    #[test]
+    fn typos_and_missing_files_are_errors() {
+        assert!(error(r#"{ "node": [] }"#).starts_with("unknown key \"node\" in scene"));
+        assert!(error(r#"{ "nodes": [{ "translate": [0, 0, 0] }] }"#).contains("unknown key \"translate\" in node"));
+        assert!(error(r#"{ "meshes": { "cube": "models/missing.obj" } }"#).starts_with("mesh \"cube\": models/missing.obj"));
+    }
+}
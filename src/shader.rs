@@ -0,0 +1,244 @@
+use glam::{Vec2, Vec3, Vec4, Mat3, Mat4, Vec4Swizzles};
+
+use crate::clipping::ClipVertex;
+use crate::color::Color;
+use crate::cubemap::CubeMap;
+use crate::fog::Fog;
+use crate::lighting::{Light, ShadingMode, AMBIENT};
+use crate::mesh::Vertex;
+use crate::skeleton::skin_vertex;
+
+// Data passed from the vertex to the fragment shader, interpolated across the triangle
+pub trait Varyings: Copy {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+// U is the uniforms type, set once per draw and shared by both shaders
+pub trait VertexShader<U> {
+    type Varyings: Varyings;
+    // Returns the view space position, the renderer does the projection
+    fn vertex(&self, vertex: &Vertex, uniforms: &U) -> (Vec3, Self::Varyings);
+}
+
+// Output is usually a Color, any type works as long as the render target can store it
+pub trait FragmentShader<U> {
+    type Varyings: Varyings;
+    type Output;
+    // None discards the pixel
+    fn fragment(&self, varyings: &Self::Varyings, uniforms: &U) -> Option<Self::Output>;
+}
+
+// Vertex after the vertex shader, pos is in view space until projected, canvas space after
+#[derive(Clone, Copy, Debug)]
+pub struct RasterVertex<V> {
+    pub pos: Vec3,
+    pub var: V,
+}
+impl<V: Varyings> ClipVertex for RasterVertex<V> {
+    fn pos(&self) -> Vec3 {
+        return self.pos;
+    }
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self { pos: self.pos.lerp(other.pos, t), var: self.var.lerp(&other.var, t) }
+    }
+}
+
+// Default shader pair, textured with Gouraud or Phong lighting, shadows and fog
+pub struct DefaultUniforms<'a> {
+    pub mat_model: Mat4,
+    pub mat_view: Mat4,
+    pub mat_normal: Mat3,   // Inverse transpose of the model matrix
+    pub joints: &'a [Mat4], // Skinning matrices, see Renderer::pose, empty for rigid meshes
+    pub diffuse: Color,
+    pub texture: Option<&'a (Vec<u32>, u32, u32)>,   // Untextured uses the vertex color
+    pub normal_map: Option<&'a (Vec<u32>, u32, u32)>,   // Tangent space, forces per pixel lighting
+    pub alpha_cutoff: Option<f32>,
+    pub shading: ShadingMode,
+    pub lights: &'a [Light],
+    pub fog: &'a Fog,
+    pub environment: Option<&'a CubeMap>,
+    pub reflectivity: f32,  // How much of the environment is mixed in, 0 = none
+    pub camera_pos: Vec3,   // World space, for the reflected view vector
+    pub unlit: bool,        // Skips lighting and reflections, fog is still applied
+    pub material_id: u16,   // Only written by the deferred path
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultVaryings {
+    pub world: Vec3,
+    pub normal: Vec3,
+    pub tangent: Vec4,  // World space, w is the bitangent sign
+    pub texture: Vec2,
+    pub color: Color,
+    pub lit: Color,     // Light reaching the vertex, only used by Gouraud shading
+}
+impl Varyings for DefaultVaryings {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            world: self.world.lerp(other.world, t),
+            normal: self.normal.lerp(other.normal, t),
+            tangent: self.tangent.lerp(other.tangent, t),
+            texture: self.texture.lerp(other.texture, t),
+            color: self.color.lerp(other.color, t),
+            lit: self.lit.lerp(other.lit, t),
+        }
+    }
+}
+
+pub struct DefaultVertexShader;
+impl<'a> VertexShader<DefaultUniforms<'a>> for DefaultVertexShader {
+    type Varyings = DefaultVaryings;
+    fn vertex(&self, vertex: &Vertex, u: &DefaultUniforms<'a>) -> (Vec3, DefaultVaryings) {
+        // Skinning and model transform
+        let (pos, normal, tangent) = skin_vertex(vertex, u.joints);
+        let world = (u.mat_model * pos.extend(1.0)).xyz();
+        let normal = (u.mat_normal * normal).normalize();
+        let tangent = (Mat3::from_mat4(u.mat_model) * tangent).normalize_or_zero().extend(vertex.tangent.w);
+
+        // Diffuse light, only used by Gouraud shading
+        // Shadowed lights are always done per pixel
+        let mut lit = Color::BLACK;
+        if !per_pixel(u) && !u.unlit {
+            for light in u.lights.iter().filter(|l| l.shadow.is_none()) {
+                lit += light.radiance(normal, world);
+            }
+        }
+
+        // Specular light
+
+        let var = DefaultVaryings { world, normal, tangent, texture: vertex.texture, color: vertex.color * u.diffuse, lit };
+        return (u.mat_view.transform_point3(world), var);
+    }
+}
+
+pub struct DefaultFragmentShader;
+impl<'a> FragmentShader<DefaultUniforms<'a>> for DefaultFragmentShader {
+    type Varyings = DefaultVaryings;
+    type Output = Color;
+    fn fragment(&self, v: &DefaultVaryings, u: &DefaultUniforms<'a>) -> Option<Color> {
+        let color = albedo(v, u)?;
+        let depth = u.mat_view.row(2).dot(v.world.extend(1.0)).abs();
+        if u.unlit {
+            return Some(u.fog.apply(color, depth, v.world.y));
+        }
+
+        // Calculate light, per vertex (already interpolated) or per pixel
+        let normal = surface_normal(v, u);
+        let per_pixel = per_pixel(u);
+        let mut lit = if per_pixel { Color::BLACK } else { v.lit };
+        for light in u.lights.iter() {
+            if per_pixel || light.shadow.is_some() {
+                lit += light.radiance_shadowed(normal, v.world);
+            }
+        }
+        let mut color = color.mul_rgb(AMBIENT + lit);
+
+        // Environment reflection
+        if let Some(environment) = u.environment.filter(|_| u.reflectivity > 0.0) {
+            let view = (v.world - u.camera_pos).normalize();
+            let reflected = environment.sample(view - 2.0 * view.dot(normal) * normal);
+            color = Color { a: color.a, ..color.lerp(reflected, u.reflectivity) };
+        }
+
+        // Fog
+        return Some(u.fog.apply(color, depth, v.world.y));
+    }
+}
+
+// Nearest texel, uv is expected to be in 0..1
+pub fn sample(texture: &(Vec<u32>, u32, u32), uv: Vec2) -> Color {
+    let (pixels, width, height) = texture;
+    let (tx, ty) = (((width - 1) as f32 * uv.x).round() as u32, ((height - 1) as f32 * uv.y).round() as u32);
+    return Color::from_u32(pixels[(tx + ty * width) as usize]);
+}
+
+fn per_pixel(u: &DefaultUniforms) -> bool {
+    return u.shading == ShadingMode::Phong || u.normal_map.is_some();
+}
+
+// Interpolated normal, perturbed by the normal map if there is one
+pub fn surface_normal(v: &DefaultVaryings, u: &DefaultUniforms) -> Vec3 {
+    let n = v.normal.normalize();
+    let Some(normal_map) = u.normal_map else { return n };
+    if v.tangent.xyz() == Vec3::ZERO {
+        return n;
+    }
+
+    // Tangent space -> world space
+    let t = (v.tangent.xyz() - n * n.dot(v.tangent.xyz())).normalize();
+    let b = n.cross(t) * v.tangent.w.signum();
+    let c = sample(normal_map, v.texture);
+    let m = Vec3::new(c.r, c.g, c.b) * 2.0 - Vec3::ONE;
+    return (t * m.x + b * m.y + n * m.z).normalize();
+}
+
+// Texture times vertex color, None if it fails the alpha test
+pub fn albedo(v: &DefaultVaryings, u: &DefaultUniforms) -> Option<Color> {
+    let color = match u.texture {
+        Some(texture) => sample(texture, v.texture) * v.color,
+        None => v.color,
+    };
+
+    // Alpha test
+    if let Some(cutoff) = u.alpha_cutoff {
+        if color.a < cutoff {
+            return None;
+        }
+    }
+    return Some(color);
+}
+
+// Debug shader pair, shows world space normals as colors
+pub struct NormalUniforms {
+    pub mat_model: Mat4,
+    pub mat_view: Mat4,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct NormalVaryings {
+    pub normal: Vec3,
+}
+impl Varyings for NormalVaryings {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self { normal: self.normal.lerp(other.normal, t) }
+    }
+}
+
+pub struct NormalShader;
+impl VertexShader<NormalUniforms> for NormalShader {
+    type Varyings = NormalVaryings;
+    fn vertex(&self, vertex: &Vertex, u: &NormalUniforms) -> (Vec3, NormalVaryings) {
+        let normal = Mat3::from_mat4(u.mat_model).inverse().transpose() * vertex.normal;
+        return (u.mat_view.transform_point3(u.mat_model.transform_point3(vertex.pos)), NormalVaryings { normal });
+    }
+}
+impl FragmentShader<NormalUniforms> for NormalShader {
+    type Varyings = NormalVaryings;
+    type Output = Color;
+    fn fragment(&self, v: &NormalVaryings, _: &NormalUniforms) -> Option<Color> {
+        let n = v.normal.normalize() * 0.5 + Vec3::splat(0.5);
+        return Some(Color::rgb(n.x, n.y, n.z));
+    }
+}
+impl<'a> FragmentShader<DefaultUniforms<'a>> for NormalShader {
+    type Varyings = DefaultVaryings;
+    type Output = Color;
+    fn fragment(&self, v: &DefaultVaryings, _: &DefaultUniforms<'a>) -> Option<Color> {
+        let n = v.normal.normalize() * 0.5 + Vec3::splat(0.5);
+        return Some(Color::rgb(n.x, n.y, n.z));
+    }
+}
+
+// Runs two fragment shaders on the same varyings, for writing two render targets at once
+pub struct Pair<A, B>(pub A, pub B);
+impl<U, A, B> FragmentShader<U> for Pair<A, B>
+where
+    A: FragmentShader<U, Output = Color>,
+    B: FragmentShader<U, Varyings = A::Varyings, Output = Color>,
+{
+    type Varyings = A::Varyings;
+    type Output = [Color; 2];
+    fn fragment(&self, v: &A::Varyings, u: &U) -> Option<[Color; 2]> {
+        return Some([self.0.fragment(v, u)?, self.1.fragment(v, u)?]);
+    }
+}
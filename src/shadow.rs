@@ -0,0 +1,118 @@
+use glam::{Mat4, Vec3, Vec4Swizzles};
+
+use crate::canvas::{Canvas, HEIGHT, WIDTH};
+use crate::clipping::clip_triangle;
+use crate::mesh::Mesh;
+
+// Depth seen from a light, rendered before the main pass and looked up per pixel
+// Depth is stored as 1 - ndc z so that closer is bigger, same as the main canvas
+pub struct ShadowMap {
+    pub depth: Canvas,  // Only the depth buffer is used
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub bias: f32,
+    pub pcf: i32,       // Filter radius in texels, 0 = hard shadows
+    pub extent: f32,    // Half height of the area covered by a directional light
+    pub far: f32,
+}
+impl ShadowMap {
+    pub fn new() -> Self {
+        Self {
+            depth: Canvas::new(),
+            view: Mat4::IDENTITY,
+            proj: Mat4::IDENTITY,
+            bias: 0.002,
+            pcf: 1,
+            extent: 3.0,
+            far: 20.0,
+        }
+    }
+    // Casters are meshes with their model matrix
+    pub fn render(&mut self, view: Mat4, proj: Mat4, casters: &[(&Mesh, Mat4)]) {
+        self.view = view;
+        self.proj = proj;
+        self.depth.depth.fill(0.0);
+
+        for (mesh, mat_model) in casters {
+            let mat = view * *mat_model;
+            for tri in mesh.triangles.iter() {
+                // Light view space, clip everything behind the near plane
+                let mut tri_view = tri.clone();
+                for v in tri_view.v.iter_mut() {
+                    v.pos = (mat * v.pos.extend(1.0)).xyz();
+                }
+                let clipped = clip_triangle(&tri_view, &Vec3::new(0.0, 0.0, -0.1), &Vec3::new(0.0, 0.0, -1.0));
+                for tri_c in clipped.iter() {
+                    let p = tri_c.v.map(|v| self.to_map(v.pos));
+                    draw_depth_triangle(&mut self.depth, p);
+                }
+            }
+        }
+    }
+    // How much of the light reaches a world space point, 0.0 = fully shadowed
+    pub fn visibility(&self, world: Vec3) -> f32 {
+        let pos = self.view.transform_point3(world);
+        if pos.z >= 0.0 {
+            return 1.0;
+        }
+        let p = self.to_map(pos);
+        let (x, y) = (p.x as i32, p.y as i32);
+
+        // Percentage closer filtering
+        let mut lit = 0;
+        let mut samples = 0;
+        for oy in -self.pcf..=self.pcf {
+            for ox in -self.pcf..=self.pcf {
+                let (sx, sy) = (x + ox, y + oy);
+                samples += 1;
+                if sx < 0 || sy < 0 || sx >= WIDTH as i32 || sy >= HEIGHT as i32 {
+                    lit += 1;
+                    continue;
+                }
+                if self.depth.get_depth(sx, sy) <= p.z + self.bias {
+                    lit += 1;
+                }
+            }
+        }
+        return lit as f32 / samples as f32;
+    }
+    // Light view space -> shadow map pixel and depth
+    fn to_map(&self, pos: Vec3) -> Vec3 {
+        let mut p = self.proj.project_point3(pos);
+        p.z = 1.0 - p.z;
+        Canvas::viewport_to_canvas(&mut p);
+        return p;
+    }
+}
+
+fn edge(a: Vec3, b: Vec3, x: f32, y: f32) -> f32 {
+    return (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x);
+}
+
+// Depth only, no culling so both sides cast shadows
+fn draw_depth_triangle(canvas: &mut Canvas, p: [Vec3; 3]) {
+    let area = edge(p[0], p[1], p[2].x, p[2].y);
+    if area == 0.0 {
+        return;
+    }
+    let min_x = p[0].x.min(p[1].x).min(p[2].x).floor().max(0.0) as i32;
+    let max_x = p[0].x.max(p[1].x).max(p[2].x).ceil().min(WIDTH as f32 - 1.0) as i32;
+    let min_y = p[0].y.min(p[1].y).min(p[2].y).floor().max(0.0) as i32;
+    let max_y = p[0].y.max(p[1].y).max(p[2].y).ceil().min(HEIGHT as f32 - 1.0) as i32;
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let w0 = edge(p[1], p[2], px, py) / area;
+            let w1 = edge(p[2], p[0], px, py) / area;
+            let w2 = edge(p[0], p[1], px, py) / area;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+            let z = w0 * p[0].z + w1 * p[1].z + w2 * p[2].z;
+            if z > canvas.get_depth(x, y) {
+                canvas.put_depth(x, y, z);
+            }
+        }
+    }
+}
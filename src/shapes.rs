@@ -4,7 +4,7 @@
 use glam::IVec2;
 
 use crate::canvas::{Canvas, WIDTH, HEIGHT};
-use crate::utils::{scale_color, add_colors};
+use crate::color::Color;
 use std::{collections::hash_map::Entry, collections::HashMap};
 
 pub fn draw_triangle(
@@ -12,9 +12,9 @@ pub fn draw_triangle(
     p1: IVec2,
     p2: IVec2,
     p3: IVec2,
-    color1: u32,
-    color2: u32,
-    color3: u32,
+    color1: Color,
+    color2: Color,
+    color3: Color,
     fill: bool,
 ) {
     let raster_data_size = cmp::max(cmp::max(p1.y, p2.y), p3.y) - cmp::min(cmp::min(p1.y, p2.y), p3.y) + 1;
@@ -27,8 +27,8 @@ pub fn draw_triangle(
 
         // Fill the triangle
         for (y, (min_x, max_x)) in raster_data {
-            let c1 = canvas.get_pixel(min_x, y);
-            let c2 = canvas.get_pixel(max_x, y);
+            let c1 = Color::from_u32(canvas.get_pixel(min_x, y));
+            let c2 = Color::from_u32(canvas.get_pixel(max_x, y));
             draw_line(canvas, IVec2::new(min_x, y), IVec2::new(max_x, y), c1, c2, None);
         }
     } else {
@@ -43,8 +43,8 @@ pub fn draw_line(
     canvas: &mut Canvas,
     p1: IVec2,
     p2: IVec2,
-    color1: u32,
-    color2: u32,
+    color1: Color,
+    color2: Color,
     mut raster_data: Option<&mut HashMap<i32, (i32, i32)>>,
 ) {
     let dx: i32 = i32::abs(p2.x - p1.x);
@@ -59,12 +59,7 @@ pub fn draw_line(
     let length = (p1 - p2).abs().max_element() + 1;
 
     for i in 0..length {
-        let ni = length - i;
-        let d1 = ni as f32 / length as f32;
-        let d2 = i as f32 / length as f32;
-        let c1 = scale_color(color1, d1);
-        let c2 = scale_color(color2, d2);
-        let color = add_colors(c1, c2);
+        let color = color1.lerp(color2, i as f32 / length as f32);
         canvas.put_pixel(current_x, current_y, f32::MAX, color);
 
         if raster_data.is_some() {
@@ -1,66 +1,62 @@
 #![allow(dead_code)]
-use std::{cmp, mem};
-use glam::{IVec2, Vec2, Vec3, Vec3Swizzles, Vec4Swizzles, Vec4};
+use std::cmp;
+use glam::{IVec2, Vec3Swizzles};
 
-use crate::canvas::{Canvas, WIDTH, HEIGHT};
+use crate::canvas::RenderTarget;
+use crate::clipping::ClipVertex;
+use crate::color::BlendMode;
 use crate::mesh::Triangle;
-use crate::utils::{scale_color, add_colors};
+use crate::shader::{FragmentShader, RasterVertex, Varyings};
 use std::{collections::hash_map::Entry, collections::HashMap};
 
 // 0 = min, 1 = max
-pub struct RasterData {
+pub struct RasterData<V> {
     x: (i32, i32),
-    tex_min: (f32, f32),
-    tex_max: (f32, f32),
-    light: (f32, f32),
-    depth: (f32, f32),
+    min: RasterVertex<V>,
+    max: RasterVertex<V>,
 }
-impl RasterData {
-    pub fn init(x: i32, tex_x: f32, tex_y: f32, light: f32, depth: f32) -> Self {
-        Self {  x: (x, x), tex_min: (tex_x, tex_y), tex_max: (tex_x, tex_y), light: (light, light), depth: (depth, depth) }
+impl<V: Copy> RasterData<V> {
+    pub fn init(x: i32, v: RasterVertex<V>) -> Self {
+        Self { x: (x, x), min: v, max: v }
     }
 }
 
-pub fn draw_triangle_tex(
-    canvas: &mut Canvas,
-    tri: &Triangle,
-    texture: &Vec<u32>,
-    tex_size: (u32, u32)
+// Triangle in canvas space, varyings are interpolated and passed to the fragment shader
+pub fn draw_triangle_tex<U, V: Varyings, FS: FragmentShader<U, Varyings = V>, T: RenderTarget<FS::Output>>(
+    target: &mut T,
+    tri: &Triangle<RasterVertex<V>>,
+    shader: &FS,
+    uniforms: &U,
+    blend: BlendMode,
 ) {
-    let (p1, p2, p3, z1, z2, z3) = (
-        tri.v[0].pos.xy().as_ivec2(), tri.v[1].pos.xy().as_ivec2(), tri.v[2].pos.xy().as_ivec2(), tri.v[0].pos.z, tri.v[1].pos.z, tri.v[2].pos.z
-    );
-    let (t1, t2, t3) = (tri.v[0].texture, tri.v[1].texture, tri.v[2].texture);
-    let (l1, l2, l3) = (tri.v[0].lit, tri.v[1].lit, tri.v[2].lit);
+    let (p1, p2, p3) = (tri.v[0].pos.xy().as_ivec2(), tri.v[1].pos.xy().as_ivec2(), tri.v[2].pos.xy().as_ivec2());
+    let (v1, v2, v3) = (&tri.v[0], &tri.v[1], &tri.v[2]);
 
     let raster_data_size = cmp::max(cmp::max(p1.y, p2.y), p3.y) - cmp::min(cmp::min(p1.y, p2.y), p3.y) + 1;
-    let mut raster_data: HashMap<i32, RasterData> = HashMap::with_capacity(raster_data_size as usize);
+    let mut raster_data: HashMap<i32, RasterData<V>> = HashMap::with_capacity(raster_data_size as usize);
 
-    draw_line_tex(canvas, p1, p2, z1, z2, t1, t2, l1, l2, texture, tex_size, Some(&mut raster_data));
-    draw_line_tex(canvas, p1, p3, z1, z3, t1, t3, l1, l3, texture, tex_size, Some(&mut raster_data));
-    draw_line_tex(canvas, p2, p3, z2, z3, t2, t3, l2, l3, texture, tex_size, Some(&mut raster_data));
+    draw_line_tex(target, p1, p2, v1, v2, shader, uniforms, blend, Some(&mut raster_data));
+    draw_line_tex(target, p1, p3, v1, v3, shader, uniforms, blend, Some(&mut raster_data));
+    draw_line_tex(target, p2, p3, v2, v3, shader, uniforms, blend, Some(&mut raster_data));
 
     // Fill the triangle
     for (y, data) in raster_data {
-        draw_line_tex(canvas, IVec2::new(data.x.0, y), IVec2::new(data.x.1, y), data.depth.0, data.depth.1, data.tex_min.into(), data.tex_max.into(), data.light.0, data.light.1, texture, tex_size, None);
+        draw_line_tex(target, IVec2::new(data.x.0, y), IVec2::new(data.x.1, y), &data.min, &data.max, shader, uniforms, blend, None);
     }
 }
 
 // Inlining this seems to boost performance by ~1ms
 #[inline(always)]
-pub fn draw_line_tex(
-    canvas: &mut Canvas,
+pub fn draw_line_tex<U, V: Varyings, FS: FragmentShader<U, Varyings = V>, T: RenderTarget<FS::Output>>(
+    target: &mut T,
     p1: IVec2,
     p2: IVec2,
-    z1: f32,
-    z2: f32,
-    t1: Vec2,
-    t2: Vec2,
-    l1: f32,
-    l2: f32,
-    texture: &Vec<u32>,
-    tex_size: (u32, u32),
-    mut raster_data: Option<&mut HashMap<i32, RasterData>>,
+    v1: &RasterVertex<V>,
+    v2: &RasterVertex<V>,
+    shader: &FS,
+    uniforms: &U,
+    blend: BlendMode,
+    mut raster_data: Option<&mut HashMap<i32, RasterData<V>>>,
 ) {
     let dx: i32 = i32::abs(p2.x - p1.x);
     let dy: i32 = i32::abs(p2.y - p1.y);
@@ -74,38 +70,16 @@ pub fn draw_line_tex(
     let length = (p1 - p2).abs().max_element() + 1;
 
     for i in 0..length {
-        let mut tex_step_x: f32 = t1.x;
-        let mut tex_step_y: f32 = t1.y;
-
-        // Calculate texture step
-        if t1.x > t2.x {
-            tex_step_x = t1.x - (i as f32 * ((t2.x - t1.x).abs() / length as f32));
-        } else if t2.x > t1.x {
-            tex_step_x = t1.x + (i as f32 * ((t2.x - t1.x).abs() / length as f32));
+        // Interpolate depth and varyings
+        let v = v1.lerp(v2, i as f32 / length as f32);
+
+        // Edges only collect raster data, every pixel gets drawn once by the fill
+        // Depth is tested before running the shader
+        if raster_data.is_none() && v.pos.z > target.canvas().get_depth(current_x, current_y) {
+            if let Some(out) = shader.fragment(&v.var, uniforms) {
+                target.write(current_x, current_y, v.pos.z, out, blend);
+            }
         }
-        if t1.y > t2.y {
-            tex_step_y = t1.y - (i as f32 * ((t2.y - t1.y).abs() / length as f32));
-        } else if t2.y > t1.y {
-            tex_step_y = t1.y + (i as f32 * ((t2.y - t1.y).abs() / length as f32));
-        } 
-        let (tx, ty) = (((tex_size.0 - 1) as f32 * tex_step_x).round() as u32, ((tex_size.1 - 1) as f32 * tex_step_y).round() as u32);
-        //println!("tex: {:?} {:?} | i: {} | texstep: {} {} | texcoord: {} {} | length: {}", t1, t2, i, tex_step_x, tex_step_y, tx, ty, length);
-
-        // Calculate light step
-        let ni = length - i;
-        let d1 = ni as f32 / length as f32;
-        let d2 = i as f32 / length as f32;
-        let light = (l1 * d1) + (l2 * d2);
-        //println!("{} {} {} {} {}", l1, l2, d1, d2, light);
-
-        // Calculate depth step
-        let mut depth_step = z1;
-        if z1 > z2 {
-            depth_step = z1 - (i as f32 * ((z2 - z1).abs() / length as f32));
-        } else if z2 > z1 {
-            depth_step = z1 + (i as f32 * ((z2 - z1).abs() / length as f32));
-        } 
-        canvas.put_pixel(current_x, current_y, depth_step, scale_color(texture[(tx + ty * tex_size.0) as usize], (0.1 + light).clamp(0.0, 1.0)));
 
         if raster_data.is_some() {
             let raster_data = raster_data.as_mut().unwrap();
@@ -114,19 +88,15 @@ pub fn draw_line_tex(
                     let cur = o.into_mut();
                     if current_x < cur.x.0 {
                         cur.x.0 = current_x;
-                        cur.tex_min = (tex_step_x, tex_step_y);
-                        cur.light.0 = light;
-                        cur.depth.0 = depth_step;
+                        cur.min = v;
                     }
                     if current_x > cur.x.1 {
                         cur.x.1 = current_x;
-                        cur.tex_max = (tex_step_x, tex_step_y);
-                        cur.light.1 = light;
-                        cur.depth.1 = depth_step;
+                        cur.max = v;
                     }
                     cur
                 }
-                Entry::Vacant(v) => v.insert(RasterData::init(current_x, tex_step_x, tex_step_y, light, depth_step)),
+                Entry::Vacant(e) => e.insert(RasterData::init(current_x, v)),
             };
         }
 
@@ -0,0 +1,446 @@
+use glam::{Vec3, Vec4, DVec3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::mesh::{Mesh, Triangle, Vertex};
+
+// Border and seam edges get this much more weight so they keep their shape
+const BORDER_WEIGHT: f64 = 100.0;
+
+// Stops at whichever comes first
+#[derive(Clone, Copy, Debug)]
+pub struct SimplifyTarget {
+    pub triangles: usize,
+    pub max_error: f32,     // Distance in model units, f32::INFINITY to only stop at the count
+                            // (root of the weighted mean squared distance to the original planes)
+}
+
+// Quadric error metric edge collapse (Garland & Heckbert), every collapse moves a vertex onto
+// one of its neighbours so uvs, normals and colors stay those of the original vertices.
+// Vertices on borders and uv seams can only slide along them. Morph targets are dropped.
+pub fn simplify(mesh: &Mesh, target: SimplifyTarget) -> Mesh {
+    let mut s = Simplifier::new(mesh);
+    s.run(target);
+    let mut simplified = s.mesh();
+    simplified.skeleton = mesh.skeleton.clone();
+    simplified.clips = mesh.clips.clone();
+    return simplified;
+}
+
+// Symmetric 4x4 matrix (upper triangle) and the total face area summed into it
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    q: [f64; 10],
+    weight: f64,
+}
+impl Quadric {
+    // Squared distance to the plane n.p + d = 0, n is expected to be normalized
+    fn plane(n: DVec3, d: f64, weight: f64) -> Self {
+        let (a, b, c) = (n.x, n.y, n.z);
+        let q = [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d];
+        return Self { q: q.map(|v| v * weight), weight };
+    }
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..10 {
+            self.q[i] += other.q[i];
+        }
+        self.weight += other.weight;
+    }
+    // Border and seam planes add their cost without counting towards the mean, heavy as they
+    // are they'd otherwise water down the distance to everything else
+    fn constraint(n: DVec3, d: f64, weight: f64) -> Self {
+        return Self { weight: 0.0, ..Self::plane(n, d, weight) };
+    }
+    // Mean squared distance weighted by face area, plus the constraint costs on top
+    fn error(&self, p: Vec3) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let q = &self.q;
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        let e = q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9];
+        return e.max(0.0) / self.weight;
+    }
+}
+
+// Collapse of position u onto position v, ordered so the heap pops the cheapest first
+struct Collapse {
+    cost: f64,
+    u: usize,
+    v: usize,
+    versions: (u32, u32),
+}
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cost == other.cost;
+    }
+}
+impl Eq for Collapse {}
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other.cost.total_cmp(&self.cost);
+    }
+}
+
+// Vertices are welded twice: attributes (position, uv and color) and positions.
+// A position with several attribute vertices lies on a seam. Normals are kept per face corner
+// so hard edges don't stop collapses.
+struct Simplifier {
+    vertices: Vec<Vertex>,
+    vertex_pos: Vec<usize>,     // Attribute vertex -> position
+    positions: Vec<Vec3>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,         // Bumped when a position changes, older heap entries are stale
+    faces: Vec<[usize; 3]>,     // Attribute vertices
+    normals: Vec<[Vec3; 3]>,
+    alive: Vec<bool>,
+    position_faces: Vec<Vec<usize>>,
+    live: usize,
+    heap: BinaryHeap<Collapse>,
+}
+impl Simplifier {
+    fn new(mesh: &Mesh) -> Self {
+        let mut s = Self {
+            vertices: Vec::new(),
+            vertex_pos: Vec::new(),
+            positions: Vec::new(),
+            quadrics: Vec::new(),
+            versions: Vec::new(),
+            faces: Vec::new(),
+            normals: Vec::new(),
+            alive: Vec::new(),
+            position_faces: Vec::new(),
+            live: 0,
+            heap: BinaryHeap::new(),
+        };
+
+        // Weld
+        let mut vertex_ids: HashMap<[u32; 9], usize> = HashMap::new();
+        let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+        for tri in mesh.triangles.iter() {
+            let face = tri.v.map(|v| {
+                let key = [
+                    v.pos.x, v.pos.y, v.pos.z, v.texture.x, v.texture.y, v.color.r, v.color.g, v.color.b, v.color.a,
+                ].map(f32::to_bits);
+                return *vertex_ids.entry(key).or_insert_with(|| {
+                    let p = *position_ids.entry(v.pos.to_array().map(f32::to_bits)).or_insert_with(|| {
+                        s.positions.push(v.pos);
+                        s.position_faces.push(Vec::new());
+                        s.positions.len() - 1
+                    });
+                    s.vertices.push(v);
+                    s.vertex_pos.push(p);
+                    s.vertices.len() - 1
+                });
+            });
+            let p = face.map(|i| s.vertex_pos[i]);
+            if p[0] == p[1] || p[1] == p[2] || p[2] == p[0] {
+                continue;
+            }
+            for i in p {
+                s.position_faces[i].push(s.faces.len());
+            }
+            s.faces.push(face);
+            s.normals.push(tri.v.map(|v| v.normal));
+            s.alive.push(true);
+        }
+        s.live = s.faces.len();
+        s.quadrics = vec![Quadric::default(); s.positions.len()];
+        s.versions = vec![0; s.positions.len()];
+
+        // Plane of every face, weighted by area
+        for f in 0..s.faces.len() {
+            let p = s.face_positions(f).map(|p| p.as_dvec3());
+            let cross = (p[1] - p[0]).cross(p[2] - p[0]);
+            let area = cross.length() * 0.5;
+            if area == 0.0 {
+                continue;
+            }
+            let n = cross / (area * 2.0);
+            let q = Quadric::plane(n, -n.dot(p[0]), area);
+            for i in s.faces[f] {
+                s.quadrics[s.vertex_pos[i]].add(&q);
+            }
+        }
+
+        // Border and seam edges, an attribute edge used by one face, get a plane perpendicular
+        // to the face so moving off the edge is expensive
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for face in s.faces.iter() {
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        for f in 0..s.faces.len() {
+            let face = s.faces[f];
+            let p = s.face_positions(f).map(|p| p.as_dvec3());
+            let normal = (p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero();
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                if edges[&(a.min(b), a.max(b))] != 1 {
+                    continue;
+                }
+                let (pa, pb) = (p[i], p[(i + 1) % 3]);
+                let n = (pb - pa).cross(normal).normalize_or_zero();
+                let q = Quadric::constraint(n, -n.dot(pa), (pb - pa).length_squared() * BORDER_WEIGHT);
+                s.quadrics[s.vertex_pos[a]].add(&q);
+                s.quadrics[s.vertex_pos[b]].add(&q);
+            }
+        }
+
+        for u in 0..s.positions.len() {
+            for v in s.neighbours(u) {
+                s.push(u, v);
+            }
+        }
+        return s;
+    }
+
+    fn run(&mut self, target: SimplifyTarget) {
+        let max_error = (target.max_error as f64).powi(2);
+        while self.live > target.triangles {
+            let Some(c) = self.heap.pop() else { break };
+            if c.versions != (self.versions[c.u], self.versions[c.v]) {
+                continue;
+            }
+            if c.cost > max_error {
+                break;
+            }
+            self.collapse(c.u, c.v);
+        }
+    }
+
+    fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new();
+        for f in (0..self.faces.len()).filter(|&f| self.alive[f]) {
+            let mut v = self.faces[f].map(|i| self.vertices[i]);
+            for (vertex, normal) in v.iter_mut().zip(self.normals[f]) {
+                vertex.normal = normal;
+            }
+            mesh.triangles.push(Triangle::new(v[0], v[1], v[2]));
+        }
+        if self.vertices.iter().any(|v| v.tangent != Vec4::ZERO) {
+            mesh.generate_tangents();
+        }
+        mesh.compute_bounds();
+        mesh.build_bvh();
+        return mesh;
+    }
+
+    fn face_positions(&self, f: usize) -> [Vec3; 3] {
+        return self.faces[f].map(|i| self.positions[self.vertex_pos[i]]);
+    }
+
+    fn live_faces(&self, p: usize) -> impl Iterator<Item = usize> + '_ {
+        return self.position_faces[p].iter().copied().filter(|&f| self.alive[f]);
+    }
+
+    fn has_position(&self, f: usize, p: usize) -> bool {
+        return self.faces[f].iter().any(|&i| self.vertex_pos[i] == p);
+    }
+
+    fn neighbours(&self, p: usize) -> Vec<usize> {
+        let mut n: Vec<usize> = self.live_faces(p)
+            .flat_map(|f| self.faces[f].map(|i| self.vertex_pos[i]))
+            .filter(|&q| q != p)
+            .collect();
+        n.sort_unstable();
+        n.dedup();
+        return n;
+    }
+
+    fn push(&mut self, u: usize, v: usize) {
+        let mut q = self.quadrics[u];
+        q.add(&self.quadrics[v]);
+        let cost = q.error(self.positions[v]);
+        self.heap.push(Collapse { cost, u, v, versions: (self.versions[u], self.versions[v]) });
+    }
+
+    // Moves position u onto v if that keeps the mesh manifold, borders and seams intact
+    // and doesn't flip any triangle
+    fn collapse(&mut self, u: usize, v: usize) -> bool {
+        let faces: Vec<usize> = self.live_faces(u).collect();
+        let (shared, moved): (Vec<usize>, Vec<usize>) = faces.iter().partition(|&&f| self.has_position(f, v));
+        if shared.is_empty() {
+            return false;
+        }
+
+        // Vertices on a border can only move along it
+        let edge_faces = |a: usize, b: usize| faces.iter().filter(|&&f| self.has_position(f, a) && self.has_position(f, b)).count();
+        let border = self.neighbours(u).into_iter().any(|w| edge_faces(u, w) == 1);
+        if border && shared.len() != 1 {
+            return false;
+        }
+
+        // Link condition, the only common neighbours are the ones across the collapsed edge
+        let nv = self.neighbours(v);
+        let common = self.neighbours(u).into_iter().filter(|w| nv.binary_search(w).is_ok()).count();
+        if common != shared.len() {
+            return false;
+        }
+
+        // Each attribute vertex of u turns into the one of v on the same side of any seam,
+        // along with the normals of both in the collapsed face
+        let mut remap: HashMap<usize, (usize, Vec3, Vec3)> = HashMap::new();
+        for &f in shared.iter() {
+            let face = self.faces[f];
+            let a = face.iter().position(|&i| self.vertex_pos[i] == u).unwrap();
+            let b = face.iter().position(|&i| self.vertex_pos[i] == v).unwrap();
+            let entry = remap.entry(face[a]).or_insert((face[b], self.normals[f][a], self.normals[f][b]));
+            if entry.0 != face[b] {
+                return false;
+            }
+        }
+        for &f in moved.iter() {
+            if self.faces[f].iter().any(|&i| self.vertex_pos[i] == u && !remap.contains_key(&i)) {
+                return false;
+            }
+        }
+
+        // Flipped or degenerate triangles
+        let target = self.positions[v];
+        for &f in moved.iter() {
+            let before = self.face_positions(f);
+            let after = self.faces[f].map(|i| if self.vertex_pos[i] == u { target } else { self.positions[self.vertex_pos[i]] });
+            let n0 = (before[1] - before[0]).cross(before[2] - before[0]);
+            let n1 = (after[1] - after[0]).cross(after[2] - after[0]);
+            if n1.length_squared() <= f32::EPSILON * n0.length_squared() || n0.dot(n1) <= 0.0 {
+                return false;
+            }
+        }
+
+        for &f in shared.iter() {
+            self.alive[f] = false;
+            self.live -= 1;
+        }
+        for &f in moved.iter() {
+            for (i, normal) in self.faces[f].iter_mut().zip(self.normals[f].iter_mut()) {
+                let Some(&(b, from, to)) = remap.get(i) else { continue };
+                // Smooth normals follow the vertex, hard edges keep the face's own
+                if *normal == from {
+                    *normal = to;
+                }
+                *i = b;
+            }
+            self.position_faces[v].push(f);
+        }
+        let q = self.quadrics[u];
+        self.quadrics[v].add(&q);
+        self.position_faces[u].clear();
+        self.versions[u] += 1;
+
+        // Everything around v has a new cost
+        for w in self.neighbours(v) {
+            self.versions[w] += 1;
+        }
+        self.versions[v] += 1;
+        for w in self.neighbours(v) {
+            self.push(v, w);
+            self.push(w, v);
+            for x in self.neighbours(w).into_iter().filter(|&x| x != v) {
+                self.push(w, x);
+                self.push(x, w);
+            }
+        }
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec2;
+    use crate::color::Color;
+
+    // Flat 6x6 quad grid on y = 0 over x, z in 0..6, with the given vertices raised
+    fn grid(bumps: &[(i32, i32)]) -> Mesh {
+        let vertex = |x: i32, z: i32| {
+            let y = if bumps.contains(&(x, z)) { 0.5 } else { 0.0 };
+            Vertex::new(Vec3::new(x as f32, y, z as f32), Vec3::Y, Vec2::new(x as f32, z as f32) / 6.0, Color::WHITE)
+        };
+        let mut mesh = Mesh::new();
+        for x in 0..6 {
+            for z in 0..6 {
+                mesh.triangles.push(Triangle::new(vertex(x, z), vertex(x, z + 1), vertex(x + 1, z + 1)));
+                mesh.triangles.push(Triangle::new(vertex(x, z), vertex(x + 1, z + 1), vertex(x + 1, z)));
+            }
+        }
+        return mesh;
+    }
+    fn kept(mesh: &Mesh, x: i32, z: i32) -> bool {
+        let p = Vec3::new(x as f32, 0.5, z as f32);
+        return mesh.triangles.iter().any(|t| t.v.iter().any(|v| v.pos == p));
+    }
+    fn on_border(p: Vec3) -> bool {
+        return p.x == 0.0 || p.x == 6.0 || p.z == 0.0 || p.z == 6.0;
+    }
+
+    #[test]
+    fn flat_grid_keeps_its_outline() {
+        let simplified = simplify(&grid(&[]), SimplifyTarget { triangles: 0, max_error: 0.05 });
+        assert!(simplified.triangles.len() < 20, "{} triangles left", simplified.triangles.len());
+        let area: f32 = simplified.triangles.iter().map(|t| (t.v[1].pos - t.v[0].pos).cross(t.v[2].pos - t.v[0].pos).length() * 0.5).sum();
+        assert!((area - 36.0).abs() < 1e-3, "area {}", area);
+        for corner in [(0.0, 0.0), (6.0, 0.0), (0.0, 6.0), (6.0, 6.0)] {
+            let p = Vec3::new(corner.0, 0.0, corner.1);
+            assert!(simplified.triangles.iter().any(|t| t.v.iter().any(|v| v.pos == p)));
+        }
+    }
+
+    #[test]
+    fn border_bumps_over_max_error_survive() {
+        let bumps = [(1, 0), (3, 0), (5, 0)];
+        let simplified = simplify(&grid(&bumps), SimplifyTarget { triangles: 0, max_error: 0.05 });
+        for (x, z) in bumps {
+            assert!(kept(&simplified, x, z), "bump at {} {} flattened", x, z);
+        }
+        // Border vertices only slide along the border
+        assert!(simplified.triangles.iter().flat_map(|t| t.v.iter()).filter(|v| v.pos.y == 0.5).all(|v| on_border(v.pos)));
+    }
+
+    #[test]
+    fn uv_seam_is_kept() {
+        // Two uv islands split at x = 3, the seam vertices are welded by position only
+        let mut mesh = grid(&[]);
+        for (i, tri) in mesh.triangles.iter_mut().enumerate() {
+            let right = i / 12 >= 3;
+            for v in tri.v.iter_mut() {
+                v.texture.x += if right { 1.0 } else { 0.0 };
+            }
+        }
+        let simplified = simplify(&mesh, SimplifyTarget { triangles: 0, max_error: 0.05 });
+        assert!(simplified.triangles.len() < mesh.triangles.len());
+        for tri in simplified.triangles.iter() {
+            let right = tri.v[0].texture.x >= 1.0;
+            assert!(tri.v.iter().all(|v| (v.texture.x >= 1.0) == right), "triangle spans the seam");
+            assert!(tri.v.iter().all(|v| if right { v.pos.x >= 3.0 } else { v.pos.x <= 3.0 }), "seam moved");
+        }
+    }
+
+    #[test]
+    fn interior_bumps_over_max_error_survive() {
+        let bumps = [(2, 2), (4, 3), (2, 4)];
+        let simplified = simplify(&grid(&bumps), SimplifyTarget { triangles: 0, max_error: 0.05 });
+        for (x, z) in bumps {
+            assert!(kept(&simplified, x, z), "bump at {} {} flattened", x, z);
+        }
+    }
+
+    #[test]
+    fn triangle_count_overrides_max_error() {
+        let bumps = [(2, 2), (4, 3)];
+        let simplified = simplify(&grid(&bumps), SimplifyTarget { triangles: 10, max_error: f32::INFINITY });
+        assert!(simplified.triangles.len() <= 10);
+        let kept_all = simplify(&grid(&bumps), SimplifyTarget { triangles: 0, max_error: 0.0 });
+        assert!(kept(&kept_all, 2, 2) && kept(&kept_all, 4, 3));
+    }
+}
@@ -0,0 +1,161 @@
+#![allow(dead_code)]
+use glam::{Vec3, Vec4, Vec4Swizzles, Quat, Mat3, Mat4};
+
+use crate::animation::{Clip, Timeline};
+use crate::mesh::{Mesh, Vertex};
+
+// Rest pose is relative to the parent joint, or to Skeleton::root for joints without one
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub inverse_bind: Mat4,     // Model space -> joint space in the bind pose
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    pub root: Mat4,     // Transform of whatever the root joints hang from
+}
+impl Skeleton {
+    // Skinning matrices, one per joint, the rest pose if there's no clip
+    pub fn pose(&self, clip: Option<(&SkeletonClip, f32)>) -> Vec<Mat4> {
+        let mut local: Vec<(Vec3, Quat, Vec3)> = self.joints.iter().map(|j| (j.translation, j.rotation, j.scale)).collect();
+        if let Some((clip, time)) = clip {
+            for (joint, c) in clip.joints.iter() {
+                let Some((t, r, s)) = local.get_mut(*joint) else { continue };
+                c.apply(time, t, r, s);
+            }
+        }
+
+        let mut global: Vec<Option<Mat4>> = vec![None; self.joints.len()];
+        return (0..self.joints.len()).map(|i| self.global(i, &local, &mut global) * self.joints[i].inverse_bind).collect();
+    }
+    // Joints can come in any order, parents are filled in as they're needed
+    fn global(&self, i: usize, local: &[(Vec3, Quat, Vec3)], global: &mut [Option<Mat4>]) -> Mat4 {
+        if let Some(mat) = global[i] {
+            return mat;
+        }
+        let parent = match self.joints[i].parent {
+            Some(p) => self.global(p, local, global),
+            None => self.root,
+        };
+        let (t, r, s) = local[i];
+        let mat = parent * Mat4::from_scale_rotation_translation(s, r, t);
+        global[i] = Some(mat);
+        return mat;
+    }
+}
+
+// Animation of a skeleton, a transform clip for every animated joint
+#[derive(Clone, Debug)]
+pub struct SkeletonClip {
+    pub name: String,
+    pub duration: f32,
+    pub joints: Vec<(usize, Clip)>,
+}
+impl Timeline for SkeletonClip {
+    fn duration(&self) -> f32 {
+        return self.duration;
+    }
+}
+
+// Linear blend skinning, weights are expected to add up to 1
+pub fn skin_vertex(vertex: &Vertex, joints: &[Mat4]) -> (Vec3, Vec3, Vec3) {
+    let tangent = vertex.tangent.xyz();
+    if joints.is_empty() || vertex.weights == Vec4::ZERO {
+        return (vertex.pos, vertex.normal, tangent);
+    }
+    let mut skin = Mat4::ZERO;
+    for i in 0..4 {
+        if vertex.weights[i] > 0.0 {
+            skin += joints.get(vertex.joints[i] as usize).copied().unwrap_or(Mat4::IDENTITY) * vertex.weights[i];
+        }
+    }
+    let rotation = Mat3::from_mat4(skin);
+    return (skin.transform_point3(vertex.pos), rotation * vertex.normal, rotation * tangent);
+}
+
+// Copy of the mesh in the given pose, for passes that don't run the vertex shader (shadows)
+pub fn skin_mesh(mesh: &Mesh, joints: &[Mat4]) -> Mesh {
+    let mut posed = Mesh::new();
+    posed.triangles = mesh.triangles.clone();
+    for tri in posed.triangles.iter_mut() {
+        for v in tri.v.iter_mut() {
+            let (pos, normal, tangent) = skin_vertex(v, joints);
+            (v.pos, v.normal) = (pos, normal);
+            v.tangent = tangent.extend(v.tangent.w);
+        }
+    }
+    return posed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec2;
+    use crate::animation::{Interpolation, Keyframe, Track};
+    use crate::color::Color;
+
+    // Upper arm from the origin, forearm one unit up, listed child first
+    fn arm() -> Skeleton {
+        let joint = |name: &str, parent, translation: Vec3, bind: Vec3| Joint {
+            name: name.to_string(), parent, inverse_bind: Mat4::from_translation(-bind),
+            translation, rotation: Quat::IDENTITY, scale: Vec3::ONE,
+        };
+        return Skeleton {
+            joints: vec![joint("forearm", Some(1), Vec3::Y, Vec3::Y), joint("upper", None, Vec3::ZERO, Vec3::ZERO)],
+            root: Mat4::IDENTITY,
+        };
+    }
+    fn bend(joint: usize, degrees: f32) -> SkeletonClip {
+        let rotation = Track::new(vec![Keyframe::new(0.0, Quat::from_rotation_z(degrees.to_radians()))], Interpolation::Linear);
+        return SkeletonClip { name: "bend".to_string(), duration: 1.0, joints: vec![(joint, Clip::new("bend").with_tracks(Track::empty(), rotation, Track::empty()))] };
+    }
+
+    #[test]
+    fn rest_pose_is_the_bind_pose() {
+        for mat in arm().pose(None) {
+            assert!(mat.abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        }
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let joints = arm().pose(Some((&bend(1, 90.0), 0.0)));
+        // The hand, one unit above the forearm joint, swings down to the left
+        assert!(joints[0].transform_point3(Vec3::new(0.0, 2.0, 0.0)).abs_diff_eq(Vec3::new(-2.0, 0.0, 0.0), 1e-5));
+        assert!(joints[1].transform_point3(Vec3::new(0.0, 1.0, 0.0)).abs_diff_eq(Vec3::new(-1.0, 0.0, 0.0), 1e-5));
+
+        // Bending the forearm leaves the upper arm alone
+        let joints = arm().pose(Some((&bend(0, 90.0), 0.0)));
+        assert!(joints[1].abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        assert!(joints[0].transform_point3(Vec3::new(0.0, 2.0, 0.0)).abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+
+        // Clips for joints the skeleton doesn't have are ignored
+        assert_eq!(arm().pose(Some((&bend(5, 90.0), 0.0))).len(), 2);
+    }
+
+    #[test]
+    fn root_moves_everything() {
+        let mut skeleton = arm();
+        skeleton.root = Mat4::from_translation(Vec3::X);
+        for mat in skeleton.pose(None) {
+            assert!(mat.abs_diff_eq(Mat4::from_translation(Vec3::X), 1e-6));
+        }
+    }
+
+    #[test]
+    fn vertices_blend_their_joints() {
+        let joints = arm().pose(Some((&bend(0, 90.0), 0.0)));
+        let mut vertex = Vertex::new(Vec3::new(0.0, 2.0, 0.0), Vec3::Y, Vec2::ZERO, Color::WHITE);
+        assert_eq!(skin_vertex(&vertex, &joints).0, vertex.pos);
+        (vertex.joints, vertex.weights) = ([0, 1, 0, 0], Vec4::new(0.5, 0.5, 0.0, 0.0));
+        let (pos, normal, _) = skin_vertex(&vertex, &joints);
+        assert!(pos.abs_diff_eq(Vec3::new(-0.5, 1.5, 0.0), 1e-5));
+        assert!(normal.abs_diff_eq(Vec3::new(-0.5, 0.5, 0.0), 1e-5));
+    }
+}